@@ -0,0 +1,47 @@
+use anthropoki::{ApiVersion, Model};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use strands::{
+    agent::{Agent, AgentArgs},
+    message::{Message, SystemPrompt},
+    model::anthropic::AnthropicModelProvider,
+};
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct Person {
+    /// The person's full name.
+    name: String,
+    /// The person's age in years.
+    age: u32,
+    /// The person's occupation.
+    occupation: String,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let anthropic = AnthropicModelProvider::new(
+        std::env::var("ANTHROPIC_API_KEY").expect("ANTHROPIC_API_KEY must be set"),
+        ApiVersion::Latest,
+        Model::ClaudeSonnet4_5,
+    );
+
+    let mut my_agent = Agent::new(
+        anthropic,
+        AgentArgs {
+            system_prompt: Some(SystemPrompt::new(
+                "Extract details about the person described.",
+            )),
+            messages: vec![Message::new_user(
+                "John Smith is a 30 year old software engineer living in Seattle.",
+            )],
+            ..Default::default()
+        },
+    );
+
+    let person: Person = my_agent.structured_output().await?;
+    tracing::info!(person = ?person);
+
+    Ok(())
+}
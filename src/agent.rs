@@ -1,38 +1,442 @@
 use std::{
-    collections::HashMap,
+    pin::Pin,
     sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
-use futures::StreamExt;
+use futures::{Stream, StreamExt};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+pub use tokio_util::sync::CancellationToken;
 
+#[cfg(feature = "serde")]
+use crate::session::{Session, SessionArgs, SessionManager, SessionRecorder};
 use crate::{
+    conversation_manager::{
+        ConversationContext, ConversationManager, SlidingWindowConversationManager,
+    },
+    error::Error,
+    hook::{Hook, ToolCallDecision},
     mcp_client::McpClient,
     message::{
-        ContentBlock, Message, StopReason, SystemPrompt, TextBlock, ToolResultBlock,
-        ToolResultContent,
+        CachePointBlock, ContentBlock, Message, Role, StopReason, SystemPrompt, SystemPromptBlock,
+        TextBlock, ToolResult, ToolResultBlock, ToolResultContent, ToolUseBlock,
+    },
+    model::model_provider::{
+        ModelPricing, ModelProvider, ModelProviderError, ModelProviderStream, StreamArgs,
+        StreamEvent, ToolPolicy, Usage,
     },
-    model::model_provider::{ModelProvider, ModelProviderStream, StreamArgs, StreamEvent},
-    state_provider::StateProvider,
-    tool::Tool,
+    state_provider::{MemoryStateProvider, StateProvider},
+    tool::{Tool, ToolContext, ToolSpec},
 };
 
+/// Events emitted by an agent while it runs a turn.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum AgentEvent {
+    /// An event emitted by the model provider.
+    Model(StreamEvent),
+    /// A new model call and tool execution cycle has started.
+    CycleStarted,
+    /// A tool requested by the model has started executing.
+    ToolCallStarted { tool_use: ToolUseBlock },
+    /// A tool has finished executing. Failed calls carry an error result.
+    ToolCallCompleted {
+        result: ToolResultBlock,
+        duration: Duration,
+    },
+    /// The current cycle has finished.
+    CycleCompleted,
+    /// The turn has finished and the agent is waiting on the user.
+    TurnCompleted { stop_reason: StopReason },
+    /// The cost in US dollars of the model call whose usage was just reported. Only emitted
+    /// when the model's pricing is known.
+    ModelCallCost { cost: f64 },
+    /// The turn was cancelled. The conversation is left in a valid state.
+    Cancelled,
+    /// The turn was stopped early because a limit was reached. The conversation is left in a
+    /// valid state and a new turn may be started.
+    LimitReached { limit: TurnLimit },
+}
+
+/// A limit that can stop a turn early. See [`TurnLimits`].
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum TurnLimit {
+    ModelCalls,
+    ToolCalls,
+    OutputTokens,
+    Deadline,
+}
+
+impl std::fmt::Display for TurnLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TurnLimit::ModelCalls => write!(f, "model call limit reached"),
+            TurnLimit::ToolCalls => write!(f, "tool call limit reached"),
+            TurnLimit::OutputTokens => write!(f, "output token limit reached"),
+            TurnLimit::Deadline => write!(f, "turn deadline reached"),
+        }
+    }
+}
+
+/// Upper bounds for a single turn. Unset limits are not enforced.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TurnLimits {
+    /// Maximum number of model calls.
+    pub max_model_calls: Option<usize>,
+    /// Maximum number of tool calls.
+    pub max_tool_calls: Option<usize>,
+    /// Maximum number of output tokens across all model calls.
+    ///
+    /// Token counts come from the usage reported by the model provider, or are estimated from
+    /// the streamed output when no usage is reported.
+    pub max_output_tokens: Option<u64>,
+    /// Maximum wall-clock time for the turn.
+    pub deadline: Option<Duration>,
+}
+
+impl TurnLimits {
+    fn exceeded(
+        &self,
+        model_calls: usize,
+        output_tokens: u64,
+        deadline: Option<tokio::time::Instant>,
+    ) -> Option<TurnLimit> {
+        if self.max_model_calls.is_some_and(|max| model_calls >= max) {
+            Some(TurnLimit::ModelCalls)
+        } else if self
+            .max_output_tokens
+            .is_some_and(|max| output_tokens >= max)
+        {
+            Some(TurnLimit::OutputTokens)
+        } else if deadline.is_some_and(|d| tokio::time::Instant::now() >= d) {
+            Some(TurnLimit::Deadline)
+        } else {
+            None
+        }
+    }
+}
+
+/// Inference parameters sent with every model call. Unset parameters are left to the provider.
+#[derive(Clone, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct InferenceConfig {
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub stop_sequences: Option<Vec<String>>,
+}
+
+impl InferenceConfig {
+    /// Returns `overrides` with unset parameters taken from `self`.
+    fn merge(&self, overrides: InferenceConfig) -> InferenceConfig {
+        InferenceConfig {
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            temperature: overrides.temperature.or(self.temperature),
+            top_p: overrides.top_p.or(self.top_p),
+            stop_sequences: overrides
+                .stop_sequences
+                .or_else(|| self.stop_sequences.clone()),
+        }
+    }
+}
+
+/// Per-turn options that replace the agent's defaults.
+#[derive(Clone, Debug, Default)]
+pub struct TurnOptions {
+    /// Parameters that replace those set in [`AgentArgs::inference`]. Unset parameters keep the
+    /// agent's values.
+    pub inference: Option<InferenceConfig>,
+    /// Replaces [`AgentArgs::tool_policy`].
+    pub tool_policy: Option<ToolPolicy>,
+    /// Stops the turn as soon as it is cancelled. See [`Agent::turn_with_cancellation`].
+    pub cancellation: CancellationToken,
+}
+
+/// Usage totals for a turn or for the lifetime of an agent.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AgentMetrics {
+    /// Tokens reported by the model provider.
+    pub usage: Usage,
+    /// Model calls made, counting each retried attempt.
+    pub model_calls: u64,
+    /// Tool calls executed.
+    pub tool_calls: u64,
+    /// Time spent waiting on the model, as reported with its usage.
+    pub model_latency: Duration,
+    /// Cost in US dollars of the reported usage. Zero when the model's pricing is unknown.
+    pub cost: f64,
+}
+
+/// Accumulates metrics for the current turn and for the agent as a whole.
+#[derive(Debug, Default)]
+struct MetricsRecorder {
+    turn: AgentMetrics,
+    total: AgentMetrics,
+}
+
+impl MetricsRecorder {
+    fn record(&mut self, update: impl Fn(&mut AgentMetrics)) {
+        update(&mut self.turn);
+        update(&mut self.total);
+    }
+
+    /// Records the usage reported for a model call and returns its cost, if the pricing is known.
+    fn record_usage(
+        &mut self,
+        usage: &Usage,
+        latency: Duration,
+        pricing: Option<ModelPricing>,
+    ) -> Option<f64> {
+        let cost = pricing.map(|pricing| pricing.cost(usage));
+        self.record(|m| {
+            m.usage += *usage;
+            m.model_latency += latency;
+            m.cost += cost.unwrap_or_default();
+        });
+        cost
+    }
+
+    /// Fails once the agent's costs have reached `spend_limit`.
+    fn check_spend(&self, spend_limit: Option<f64>) -> crate::Result<()> {
+        match spend_limit {
+            Some(limit) if self.total.cost >= limit => Err(Error::SpendLimitExceeded {
+                spent: self.total.cost,
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The model provider handed to conversation managers, so that the calls they make are metered
+/// and limited like the agent's own.
+struct MeteredModelProvider {
+    model_provider: Arc<dyn ModelProvider>,
+    metrics: Arc<Mutex<MetricsRecorder>>,
+    pricing: Option<ModelPricing>,
+    spend_limit: Option<f64>,
+}
+
+impl ModelProvider for MeteredModelProvider {
+    fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream {
+        let mut metrics = self.metrics.lock().unwrap();
+        if let Err(e) = metrics.check_spend(self.spend_limit) {
+            return Box::pin(futures::stream::once(async { Err(e.into()) }));
+        }
+        metrics.record(|m| m.model_calls += 1);
+        drop(metrics);
+
+        let metrics = Arc::clone(&self.metrics);
+        let pricing = self.pricing;
+        Box::pin(
+            self.model_provider
+                .stream(messages, args)
+                .inspect(move |event| {
+                    if let Ok(StreamEvent::Metadata { usage, latency }) = event {
+                        metrics
+                            .lock()
+                            .unwrap()
+                            .record_usage(usage, *latency, pricing);
+                    }
+                }),
+        )
+    }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.pricing
+    }
+}
+
+/// A stream of events from an agent turn.
+pub type AgentStream = Pin<Box<dyn Stream<Item = Result<AgentEvent, ModelProviderError>> + Send>>;
+
+/// Retry behavior for model calls that fail before producing a complete message.
+///
+/// Truncated streams and transport errors (connection failures, timeouts and I/O errors) are
+/// retried with exponential backoff, as long as the failed attempt has not streamed any content
+/// yet. Other errors end the turn immediately.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RetryPolicy {
+    /// Maximum number of attempts per model call, including the first.
+    pub max_attempts: u32,
+    /// Delay before the first retry. Doubles after each failed attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delay before the next attempt, or `None` if the call should not be retried.
+    fn backoff(&self, attempt: u32, error: &ModelProviderError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !is_transient(error.as_ref()) {
+            return None;
+        }
+
+        let factor = 2u32.saturating_pow(attempt - 1);
+        Some(
+            self.initial_backoff
+                .saturating_mul(factor)
+                .min(self.max_backoff),
+        )
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+        }
+    }
+}
+
+fn is_context_overflow(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::ContextWindowOverflow)
+        ) {
+            return true;
+        }
+
+        current = error.source();
+    }
+
+    false
+}
+
+fn is_transient(error: &(dyn std::error::Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(error) = current {
+        if matches!(error.downcast_ref::<Error>(), Some(Error::TruncatedStream))
+            || error.is::<std::io::Error>()
+        {
+            return true;
+        }
+
+        if let Some(error) = error.downcast_ref::<reqwest::Error>()
+            && (error.is_connect() || error.is_timeout() || error.is_body() || error.is_request())
+        {
+            return true;
+        }
+
+        current = error.source();
+    }
+
+    false
+}
+
+/// Controls how the tool uses in a single assistant message are executed.
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+#[non_exhaustive]
+pub enum ToolExecutor {
+    /// Run tools one at a time, in the order the model requested them.
+    #[default]
+    Sequential,
+    /// Run up to `max_concurrency` tools at the same time.
+    Concurrent { max_concurrency: usize },
+}
+
+impl ToolExecutor {
+    fn max_concurrency(&self) -> usize {
+        match self {
+            ToolExecutor::Sequential => 1,
+            ToolExecutor::Concurrent { max_concurrency } => (*max_concurrency).max(1),
+        }
+    }
+}
+
 pub struct AgentArgs<E> {
     pub system_prompt: Option<SystemPrompt>,
     pub state_provider: Option<Box<dyn StateProvider>>,
     pub mcp_clients: Vec<McpClient>,
     pub messages: Vec<Message>,
     pub tools: Vec<Box<dyn Tool<E>>>,
+    /// How the tool uses of one response are executed. Defaults to [`ToolExecutor::Sequential`].
+    pub tool_executor: ToolExecutor,
+    pub hooks: Vec<Box<dyn Hook>>,
+    pub limits: TurnLimits,
+    /// Inference parameters of every turn unless overridden with [`Agent::turn_with`]. Defaults
+    /// to 4096 max tokens.
+    pub inference: InferenceConfig,
+    /// Number of times per turn that a response cut off by the max tokens limit is continued in
+    /// a new model call. Responses with tool calls are not continued.
+    ///
+    /// The prompt asking the model to continue is only sent with that call. The continuation is
+    /// merged into the truncated message, which is reported to hooks and the session again.
+    pub max_tokens_continuations: usize,
+    /// Retry policy for failed model calls. Model calls are not retried when unset.
+    pub retry: Option<RetryPolicy>,
+    /// Number of times the model may correct invalid structured output before giving up.
+    pub structured_output_retries: usize,
+    /// Keeps the conversation within the context window. Defaults to a sliding window.
+    pub conversation_manager: Option<Box<dyn ConversationManager>>,
+    /// Tool policy of every turn unless overridden with [`Agent::turn_with`].
+    pub tool_policy: Option<ToolPolicy>,
+    /// Whether the model may call several tools in one response. Providers allow it by default.
+    pub parallel_tool_use: Option<bool>,
+    /// Prices used to compute costs. Defaults to the model provider's pricing.
+    pub pricing: Option<ModelPricing>,
+    /// Spend ceiling in US dollars over the agent's lifetime. Once costs reach it, turns fail
+    /// with [`Error::SpendLimitExceeded`] before calling the model or running tools again. Turns
+    /// fail with [`Error::SpendLimitWithoutPricing`] if no pricing is known.
+    pub spend_limit: Option<f64>,
+    /// Places cache points after the system prompt, the tool definitions and the latest message
+    /// so that providers with prompt caching can reuse the conversation prefix between calls.
+    pub prompt_caching: bool,
+    /// Saves the agent's session after every message appended to the conversation.
+    #[cfg(feature = "serde")]
+    pub session: Option<SessionArgs>,
 }
 
 impl<E> std::fmt::Debug for AgentArgs<E> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("AgentArgs")
+        let mut debug = f.debug_struct("AgentArgs");
+        debug
             .field("system_prompt", &self.system_prompt)
             .field("state_provider", &"StateProvider")
             .field("mcp_clients", &self.mcp_clients)
             .field("messages", &self.messages)
             .field("tools", &"Tools")
-            .finish()
+            .field("tool_executor", &self.tool_executor)
+            .field("hooks", &"Hooks")
+            .field("limits", &self.limits)
+            .field("inference", &self.inference)
+            .field("max_tokens_continuations", &self.max_tokens_continuations)
+            .field("retry", &self.retry)
+            .field("structured_output_retries", &self.structured_output_retries)
+            .field("conversation_manager", &"ConversationManager")
+            .field("tool_policy", &self.tool_policy)
+            .field("parallel_tool_use", &self.parallel_tool_use)
+            .field("pricing", &self.pricing)
+            .field("spend_limit", &self.spend_limit)
+            .field("prompt_caching", &self.prompt_caching);
+
+        #[cfg(feature = "serde")]
+        debug.field("session", &self.session);
+
+        debug.finish()
     }
 }
 
@@ -44,6 +448,24 @@ impl Default for AgentArgs<()> {
             mcp_clients: Vec::new(),
             messages: Vec::new(),
             tools: Vec::new(),
+            tool_executor: ToolExecutor::default(),
+            hooks: Vec::new(),
+            limits: TurnLimits::default(),
+            inference: InferenceConfig {
+                max_tokens: Some(4096),
+                ..Default::default()
+            },
+            max_tokens_continuations: 0,
+            retry: None,
+            structured_output_retries: 3,
+            conversation_manager: None,
+            tool_policy: None,
+            parallel_tool_use: None,
+            pricing: None,
+            spend_limit: None,
+            prompt_caching: false,
+            #[cfg(feature = "serde")]
+            session: None,
         }
     }
 }
@@ -51,31 +473,225 @@ impl Default for AgentArgs<()> {
 pub struct Agent<E> {
     model_provider: Arc<dyn ModelProvider>,
     system_prompt: SystemPrompt,
-    state_provider: Box<dyn StateProvider>,
+    state_provider: Arc<dyn StateProvider>,
     mcp_clients: Arc<Vec<McpClient>>,
     messages: Arc<Mutex<Vec<Message>>>,
-    tools: Vec<Box<dyn Tool<E>>>,
+    tools: Arc<Vec<Box<dyn Tool<E>>>>,
+    tool_executor: ToolExecutor,
+    hooks: Arc<Vec<Box<dyn Hook>>>,
+    limits: TurnLimits,
+    inference: InferenceConfig,
+    max_tokens_continuations: usize,
+    retry: Option<RetryPolicy>,
+    structured_output_retries: usize,
+    conversation_manager: Arc<dyn ConversationManager>,
+    tool_policy: Option<ToolPolicy>,
+    parallel_tool_use: Option<bool>,
+    pricing: Option<ModelPricing>,
+    spend_limit: Option<f64>,
+    prompt_caching: bool,
+    metrics: Arc<Mutex<MetricsRecorder>>,
+    #[cfg(feature = "serde")]
+    session: Option<Arc<SessionRecorder>>,
 }
 
-impl<E> Agent<E> {
+impl<E> Agent<E>
+where
+    E: std::fmt::Debug + Send + 'static,
+{
     pub fn new(model_provider: impl ModelProvider + 'static, args: AgentArgs<E>) -> Self {
+        let system_prompt = args
+            .system_prompt
+            .unwrap_or(SystemPrompt::Text(String::new()));
+
+        #[cfg(feature = "serde")]
+        let session = args.session.map(|session_args| {
+            let mut session = Session::new(session_args.id, system_prompt.clone());
+            session.metadata = session_args.metadata;
+            Arc::new(SessionRecorder::new(session_args.manager, session))
+        });
+
+        let pricing = args.pricing.or_else(|| model_provider.pricing());
+
         Self {
             model_provider: Arc::new(model_provider),
-            system_prompt: args
-                .system_prompt
-                .unwrap_or(SystemPrompt::Text(String::new())),
+            system_prompt,
             state_provider: args
                 .state_provider
-                .unwrap_or_else(|| Box::new(HashMap::<String, serde_json::Value>::new())),
+                .map_or_else(|| Arc::new(MemoryStateProvider::new()) as _, Arc::from),
             mcp_clients: Arc::new(args.mcp_clients),
             messages: Arc::new(Mutex::new(args.messages)),
-            tools: Vec::new(),
+            tools: Arc::new(args.tools),
+            tool_executor: args.tool_executor,
+            hooks: Arc::new(args.hooks),
+            limits: args.limits,
+            inference: args.inference,
+            max_tokens_continuations: args.max_tokens_continuations,
+            retry: args.retry,
+            structured_output_retries: args.structured_output_retries,
+            conversation_manager: args.conversation_manager.map_or_else(
+                || Arc::new(SlidingWindowConversationManager::default()) as _,
+                Arc::from,
+            ),
+            tool_policy: args.tool_policy,
+            parallel_tool_use: args.parallel_tool_use,
+            pricing,
+            spend_limit: args.spend_limit,
+            prompt_caching: args.prompt_caching,
+            metrics: Arc::default(),
+            #[cfg(feature = "serde")]
+            session,
+        }
+    }
+
+    /// Rebuilds an agent from the session `id` stored by `manager`.
+    ///
+    /// The session's system prompt, messages and state replace those in `args`, and the agent
+    /// keeps saving the session through `manager` as the conversation continues.
+    #[cfg(feature = "serde")]
+    pub async fn from_session(
+        model_provider: impl ModelProvider + 'static,
+        mut args: AgentArgs<E>,
+        manager: impl SessionManager + 'static,
+        id: &str,
+    ) -> crate::Result<Self> {
+        let session = manager
+            .load(id)
+            .await?
+            .ok_or_else(|| Error::SessionNotFound(id.to_string()))?;
+
+        let state_provider = args
+            .state_provider
+            .get_or_insert_with(|| MemoryStateProvider::new().boxed());
+        for (key, value) in &session.state {
+            state_provider.set(key, value.clone()).await?;
+        }
+
+        args.system_prompt = Some(session.system_prompt.clone());
+        args.messages = session.messages.clone();
+        args.session = None;
+
+        let mut agent = Self::new(model_provider, args);
+        agent.session = Some(Arc::new(SessionRecorder::new(manager.boxed(), session)));
+        Ok(agent)
+    }
+
+    /// Returns the usage of the current or most recent turn.
+    pub fn turn_metrics(&self) -> AgentMetrics {
+        self.metrics.lock().unwrap().turn
+    }
+
+    /// Returns the usage accumulated since the agent was created.
+    pub fn metrics(&self) -> AgentMetrics {
+        self.metrics.lock().unwrap().total
+    }
+
+    pub fn turn(&mut self) -> AgentStream {
+        self.turn_with(TurnOptions::default())
+    }
+
+    /// Runs a turn that stops as soon as `cancellation` is cancelled.
+    ///
+    /// In-flight model output is discarded and unfinished tool calls are answered with error
+    /// results, so the conversation remains valid for the next turn. Dropping the stream has the
+    /// same effect on the conversation.
+    pub fn turn_with_cancellation(&mut self, cancellation: CancellationToken) -> AgentStream {
+        self.turn_with(TurnOptions {
+            cancellation,
+            ..Default::default()
+        })
+    }
+
+    /// Runs a turn with the agent's defaults replaced by those set in `options`.
+    pub fn turn_with(&mut self, options: TurnOptions) -> AgentStream {
+        self.run_turn(options, None)
+    }
+
+    /// Runs a turn that ends with the model producing a value of type `T`.
+    ///
+    /// The JSON schema of `T` is offered to the model as a forced tool. Tool inputs that do not
+    /// deserialize into `T` are returned to the model as errors so it can correct them, up to
+    /// [`AgentArgs::structured_output_retries`] times.
+    pub async fn structured_output<T>(&mut self) -> crate::Result<T>
+    where
+        T: JsonSchema + DeserializeOwned,
+    {
+        self.structured_output_with(TurnOptions::default()).await
+    }
+
+    /// Like [`Agent::structured_output`], but with the agent's defaults replaced by those set in
+    /// `options`. The tool policy is ignored, as the structured output tool is always forced.
+    pub async fn structured_output_with<T>(&mut self, options: TurnOptions) -> crate::Result<T>
+    where
+        T: JsonSchema + DeserializeOwned,
+    {
+        let mut schema = schemars::schema_for!(T);
+        schema.remove("$schema");
+
+        let description = schema
+            .get("description")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Returns the final response as a {}.", T::schema_name()));
+
+        let output = StructuredOutput {
+            spec: ToolSpec {
+                name: STRUCTURED_OUTPUT_TOOL.to_string(),
+                display_name: None,
+                description: Some(description),
+                input_schema: schema.as_object().cloned().unwrap_or_default(),
+            },
+            validate: validate_structured_output::<T>,
+            max_retries: self.structured_output_retries,
+        };
+
+        let options = TurnOptions {
+            tool_policy: Some(ToolPolicy::Specific {
+                name: STRUCTURED_OUTPUT_TOOL.to_string(),
+            }),
+            ..options
+        };
+
+        let mut stream = self.run_turn(options, Some(output));
+        let mut input = None;
+
+        while let Some(event) = stream.next().await {
+            match event.map_err(Error::from_model)? {
+                AgentEvent::Model(StreamEvent::ContentBlockComplete {
+                    block: ContentBlock::ToolUse(tool_use),
+                    ..
+                }) if tool_use.name == STRUCTURED_OUTPUT_TOOL => input = Some(tool_use.input),
+                AgentEvent::TurnCompleted { .. } => {
+                    let input = input.take().ok_or_else(|| {
+                        Error::StructuredOutput(
+                            "the model did not produce structured output".into(),
+                        )
+                    })?;
+
+                    return Ok(serde_json::from_value(input)?);
+                }
+                AgentEvent::LimitReached { limit } => {
+                    return Err(Error::StructuredOutput(limit.to_string()));
+                }
+                AgentEvent::Cancelled => {
+                    return Err(Error::StructuredOutput(CANCELLED_REASON.to_string()));
+                }
+                _ => {}
+            }
         }
+
+        Err(Error::StructuredOutput(
+            "the turn ended without structured output".into(),
+        ))
     }
 
-    pub fn turn(&mut self) -> ModelProviderStream {
+    fn run_turn(
+        &mut self,
+        options: TurnOptions,
+        structured_output: Option<StructuredOutput>,
+    ) -> AgentStream {
         let mut tool_specs = Vec::with_capacity(self.tools.len());
-        for tool in &self.tools {
+        for tool in self.tools.iter() {
             tool_specs.push(tool.spec());
         }
 
@@ -83,57 +699,426 @@ impl<E> Agent<E> {
             tool_specs.extend_from_slice(client.tool_specs());
         }
 
+        if let Some(output) = &structured_output {
+            tool_specs.push(output.spec.clone());
+        }
+
+        let prompt_caching = self.prompt_caching;
+        let inference = match options.inference {
+            Some(overrides) => self.inference.merge(overrides),
+            None => self.inference.clone(),
+        };
         let args = StreamArgs {
-            system_prompt: Some(self.system_prompt.clone()),
+            system_prompt: Some(if prompt_caching {
+                with_cache_point(self.system_prompt.clone())
+            } else {
+                self.system_prompt.clone()
+            }),
+            tool_policy: options.tool_policy.or_else(|| self.tool_policy.clone()),
+            parallel_tool_use: self.parallel_tool_use,
+            tool_cache_point: (prompt_caching && !tool_specs.is_empty())
+                .then_some(CachePointBlock::Default),
             tool_specs: (!tool_specs.is_empty()).then_some(tool_specs),
-            max_tokens: Some(4096),
+            max_tokens: inference.max_tokens,
+            temperature: inference.temperature,
+            top_p: inference.top_p,
+            stop_sequences: inference.stop_sequences,
             ..Default::default()
         };
 
         let messages = Arc::clone(&self.messages);
         let model_provider = Arc::clone(&self.model_provider);
         let mcp_clients = Arc::clone(&self.mcp_clients);
+        let tools = Arc::clone(&self.tools);
+        let tool_executor = self.tool_executor;
+        let hooks = Arc::clone(&self.hooks);
+        let limits = self.limits.clone();
+        let max_tokens_continuations = self.max_tokens_continuations;
+        let retry = self.retry.clone();
+        let cancellation = options.cancellation;
+        let conversation_manager = Arc::clone(&self.conversation_manager);
+        let state_provider = Arc::clone(&self.state_provider);
+        let metrics = Arc::clone(&self.metrics);
+        let pricing = self.pricing;
+        let spend_limit = self.spend_limit;
+        let conversation = Conversation {
+            messages: Arc::clone(&messages),
+            hooks: Arc::clone(&hooks),
+            #[cfg(feature = "serde")]
+            state: Arc::clone(&state_provider),
+            #[cfg(feature = "serde")]
+            session: self.session.clone(),
+        };
+
+        metrics.lock().unwrap().turn = AgentMetrics::default();
 
         Box::pin(async_stream::try_stream! {
+            // Without prices every call would cost nothing and the limit would never be reached.
+            if spend_limit.is_some() && pricing.is_none() {
+                Err(Error::SpendLimitWithoutPricing)?;
+            }
+
+            let deadline = limits.deadline.map(|d| tokio::time::Instant::now() + d);
+            let mut model_calls = 0;
+            let mut tool_calls = 0;
+            let mut output_tokens = 0;
+            let mut output_failures = 0;
+            let mut continuations = 0;
+            let mut continuing = false;
+            let managed_provider = MeteredModelProvider {
+                model_provider: Arc::clone(&model_provider),
+                metrics: Arc::clone(&metrics),
+                pricing,
+                spend_limit,
+            };
+
             loop {
-                let current_messages = messages.lock().unwrap().clone();
-                let mut stream = model_provider.stream(&current_messages, &args);
-                let mut assistant_message: Option<Message> = None;
+                if cancellation.is_cancelled() {
+                    yield AgentEvent::Cancelled;
+                    return;
+                }
+
+                if let Some(limit) = limits.exceeded(model_calls, output_tokens, deadline) {
+                    yield AgentEvent::LimitReached { limit };
+                    return;
+                }
+
+                let spend = metrics.lock().unwrap().check_spend(spend_limit);
+                spend?;
+
+                yield AgentEvent::CycleStarted;
+
+                let mut current_messages = messages.lock().unwrap().clone();
+                let context = ConversationContext {
+                    model_provider: &managed_provider,
+                    args: &args,
+                };
+                conversation_manager.apply(&mut current_messages, &context).await?;
+                *messages.lock().unwrap() = current_messages.clone();
+
+                // The conversation manager may have called the model too.
+                let spend = metrics.lock().unwrap().check_spend(spend_limit);
+                spend?;
+
+                let mut cycle_args = args.clone();
+                for hook in hooks.iter() {
+                    hook.before_model_call(&current_messages, &mut cycle_args).await;
+                }
+
+                if let Some(max_output_tokens) = limits.max_output_tokens {
+                    let remaining = max_output_tokens.saturating_sub(output_tokens);
+                    let remaining = u32::try_from(remaining).unwrap_or(u32::MAX);
+                    cycle_args.max_tokens =
+                        Some(cycle_args.max_tokens.map_or(remaining, |m| m.min(remaining)));
+                }
+
+                model_calls += 1;
+                let mut attempt = 1;
 
-                while let Some(result) = stream.next().await {
-                    let event = result?;
+                // Events are held back until the response has content, so that an attempt failing
+                // before that point can be retried without the consumer seeing it. Attempts that
+                // already streamed content are not retried.
+                let completed = loop {
+                    metrics.lock().unwrap().record(|m| m.model_calls += 1);
+                    let mut request = if prompt_caching {
+                        cache_latest_message(&current_messages)
+                    } else {
+                        current_messages.clone()
+                    };
+                    if continuing {
+                        request.push(Message::new_user(CONTINUE_PROMPT));
+                    }
+                    let mut stream = model_provider.stream(&request, &cycle_args);
+                    let mut held = Vec::new();
+                    let mut yielded = false;
+                    let mut output_chars = 0;
+                    let mut reported_output_tokens = None;
+                    let mut failure: Option<ModelProviderError> = None;
+                    let mut completed: Option<(Message, StopReason)> = None;
 
-                    yield event.clone();
+                    // A partial response is discarded when the turn is interrupted, which leaves
+                    // the conversation ending on the last user or tool result message.
+                    let mut interrupt = None;
+                    loop {
+                        let next = next_or_interrupt(&mut stream, deadline, &cancellation).await;
+                        let event = match next {
+                            Ok(Some(Ok(event))) => event,
+                            Ok(Some(Err(e))) => {
+                                failure = Some(e);
+                                break;
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                interrupt = Some(e);
+                                break;
+                            }
+                        };
 
-                    if let StreamEvent::MessageComplete { message, stop_reason } = event {
-                        assistant_message = Some(message.clone());
+                        output_chars += output_len(&event);
+                        let has_content = is_content(&event);
 
-                        match stop_reason {
-                            StopReason::ToolUse => break,
-                            _ => {
-                                messages.lock().unwrap().push(message);
-                                return
+                        let cost = match &event {
+                            StreamEvent::Metadata { usage, latency } => {
+                                reported_output_tokens = Some(usage.output_tokens);
+                                metrics.lock().unwrap().record_usage(usage, *latency, pricing)
                             }
+                            _ => None,
+                        };
+
+                        if let StreamEvent::MessageComplete { message, stop_reason } = &event {
+                            completed = Some((message.clone(), stop_reason.clone()));
+                        }
+
+                        held.push(AgentEvent::Model(event));
+                        if let Some(cost) = cost {
+                            held.push(AgentEvent::ModelCallCost { cost });
+                        }
+
+                        if yielded || has_content {
+                            yielded = true;
+                            for event in held.drain(..) {
+                                yield event;
+                            }
+                        }
+
+                        if completed.is_some() {
+                            break;
                         }
                     }
+                    drop(stream);
+
+                    if let Some(interrupt) = interrupt {
+                        yield AgentEvent::CycleCompleted;
+                        yield interrupt.event();
+                        return;
+                    }
+
+                    // On overflow the conversation manager gets a chance to shrink the
+                    // conversation before the call is attempted again.
+                    let overflowed = match (&completed, &failure) {
+                        (Some((_, StopReason::ContextWindowExceeded)), _) => true,
+                        (_, Some(error)) => is_context_overflow(error.as_ref()),
+                        _ => false,
+                    };
+
+                    if overflowed && !yielded {
+                        let mut reduced_messages = current_messages.clone();
+                        let context = ConversationContext {
+                            model_provider: &managed_provider,
+                            args: &cycle_args,
+                        };
+                        if !conversation_manager.reduce(&mut reduced_messages, &context).await? {
+                            break Err(Error::ContextWindowOverflow.into());
+                        }
+
+                        *messages.lock().unwrap() = reduced_messages.clone();
+                        current_messages = reduced_messages;
+                        continue;
+                    }
+
+                    if let Some(completed) = completed {
+                        for event in held.drain(..) {
+                            yield event;
+                        }
+
+                        // Providers without usage reporting leave the output token count at zero.
+                        output_tokens += reported_output_tokens
+                            .filter(|tokens| *tokens > 0)
+                            .unwrap_or_else(|| estimate_tokens(output_chars));
+                        break Ok(completed);
+                    }
+
+                    let error = failure.unwrap_or_else(|| Box::new(Error::TruncatedStream));
+                    let backoff = retry
+                        .as_ref()
+                        .filter(|_| !yielded)
+                        .and_then(|r| r.backoff(attempt, &error));
+                    let Some(backoff) = backoff else {
+                        break Err(error);
+                    };
+
+                    tracing::warn!(attempt, error = %error, "retrying model call");
+                    if let Err(interrupt) = sleep_or_interrupt(backoff, deadline, &cancellation).await {
+                        yield AgentEvent::CycleCompleted;
+                        yield interrupt.event();
+                        return;
+                    }
+
+                    attempt += 1;
+                };
+
+                let (message, stop_reason) = completed?;
+
+                for hook in hooks.iter() {
+                    hook.after_model_call(&message, &stop_reason).await;
                 }
 
-                let Some(message) = assistant_message else {
-                    // TODO: remove.
-                    panic!("Stream ended without MessageComplete event");
+                // A continuation takes the place of the truncated message it completes.
+                let message = if std::mem::take(&mut continuing) {
+                    let mut stored = messages.lock().unwrap();
+                    match stored.pop() {
+                        Some(truncated) if matches!(truncated.role, Role::Assistant) => {
+                            merge_continuation(truncated, message)
+                        }
+                        other => {
+                            stored.extend(other);
+                            message
+                        }
+                    }
+                } else {
+                    message
                 };
 
-                messages.lock().unwrap().push(message.clone());
+                // Every tool use must be answered, so calls that are skipped or interrupted receive
+                // an error result rather than being left without one. The guard also covers the
+                // stream being dropped from here on.
+                let mut pending = PendingToolResults::append(&conversation, message.clone());
+                conversation.appended(&message).await?;
+
+                let tool_uses: Vec<&ToolUseBlock> = message
+                    .content
+                    .iter()
+                    .filter_map(|block| match block {
+                        ContentBlock::ToolUse(tool_use) => Some(tool_use),
+                        _ => None,
+                    })
+                    .collect();
+
+                // The structured output tool ends the turn once its input validates. Invalid input
+                // is answered with the validation error so the model can try again.
+                if let Some(output) = &structured_output
+                    && let Some(output_use) = tool_uses.iter().find(|t| t.name == output.spec.name)
+                {
+                    let validation = (output.validate)(&output_use.input);
+
+                    pending.results.push(ToolResultBlock {
+                        id: output_use.id.clone(),
+                        content: match &validation {
+                            Ok(()) => Ok(vec![ToolResultContent::Text(TextBlock(
+                                "Structured output accepted.".to_string(),
+                            ))]),
+                            Err(e) => Err(vec![ToolResultContent::Text(TextBlock(format!(
+                                "Invalid structured output: {e}"
+                            )))]),
+                        },
+                    });
+                    let tool_result_message = pending.finish("structured output was requested");
+                    if let Some(tool_result_message) = tool_result_message {
+                        conversation.append(tool_result_message).await?;
+                    }
+
+                    yield AgentEvent::CycleCompleted;
+
+                    match validation {
+                        Ok(()) => {
+                            yield AgentEvent::TurnCompleted { stop_reason };
+                            return;
+                        }
+                        Err(e) => {
+                            output_failures += 1;
+                            if output_failures > output.max_retries {
+                                Err(Error::StructuredOutput(e))?;
+                            }
+
+                            continue;
+                        }
+                    }
+                }
+
+                // A truncated response is continued by asking the model to pick up where it stopped.
+                // Tool calls cut off mid-input cannot be answered, so those responses end the turn.
+                if matches!(stop_reason, StopReason::MaxTokens)
+                    && tool_uses.is_empty()
+                    && continuations < max_tokens_continuations
+                {
+                    pending.finish("");
+                    continuations += 1;
+                    continuing = true;
 
-                let tool_results = execute_tools(&message, &mcp_clients).await?;
-                if tool_results.is_empty() {
+                    yield AgentEvent::CycleCompleted;
+                    continue;
+                }
+
+                // Tool uses in a response that did not stop for them, e.g. one cut off by the max
+                // tokens limit, are answered without being executed.
+                if !matches!(stop_reason, StopReason::ToolUse) || tool_uses.is_empty() {
+                    if let Some(tool_result_message) = pending.finish(UNEXECUTED_REASON) {
+                        conversation.append(tool_result_message).await?;
+                    }
+
+                    yield AgentEvent::CycleCompleted;
+                    yield AgentEvent::TurnCompleted { stop_reason };
+                    return;
+                }
+
+                // The model call that asked for the tools may have reached the limit.
+                let spend = metrics.lock().unwrap().check_spend(spend_limit);
+                if let Err(e) = spend {
+                    if let Some(tool_result_message) = pending.finish(&e) {
+                        conversation.append(tool_result_message).await?;
+                    }
+                    Err(e)?;
                     return;
                 }
 
-                messages.lock().unwrap().push(Message {
-                    role: crate::message::Role::User,
-                    content: tool_results,
+                let allowed = limits.max_tool_calls.map_or(tool_uses.len(), |max| {
+                    max.saturating_sub(tool_calls).min(tool_uses.len())
                 });
+                let mut limit_reached = (allowed < tool_uses.len()).then_some(TurnLimit::ToolCalls);
+                let mut cancelled = false;
+                tool_calls += allowed;
+                metrics.lock().unwrap().record(|m| m.tool_calls += allowed as u64);
+
+                let runtime = ToolRuntime {
+                    tools: &tools,
+                    mcp_clients: &mcp_clients,
+                    hooks: &hooks,
+                    state: &state_provider,
+                };
+                let mut tool_events = execute_tools(&tool_uses[..allowed], runtime, tool_executor);
+                loop {
+                    let next = next_or_interrupt(&mut tool_events, deadline, &cancellation).await;
+                    let event = match next {
+                        Ok(Some(event)) => event,
+                        Ok(None) => break,
+                        Err(Interrupt::Deadline) => {
+                            limit_reached = Some(TurnLimit::Deadline);
+                            break;
+                        }
+                        Err(Interrupt::Cancelled) => {
+                            cancelled = true;
+                            break;
+                        }
+                    };
+
+                    if let AgentEvent::ToolCallCompleted { result, .. } = &event {
+                        pending.results.push(result.clone());
+                    }
+
+                    yield event;
+                }
+                drop(tool_events);
+
+                let tool_result_message = match limit_reached {
+                    _ if cancelled => pending.finish(CANCELLED_REASON),
+                    Some(limit) => pending.finish(limit),
+                    None => pending.finish(""),
+                };
+                if let Some(tool_result_message) = tool_result_message {
+                    conversation.append(tool_result_message).await?;
+                }
+
+                yield AgentEvent::CycleCompleted;
+
+                if cancelled {
+                    yield AgentEvent::Cancelled;
+                    return;
+                }
+
+                if let Some(limit) = limit_reached {
+                    yield AgentEvent::LimitReached { limit };
+                    return;
+                }
             }
         })
     }
@@ -141,26 +1126,428 @@ impl<E> Agent<E> {
     pub fn messages(&self) -> Vec<Message> {
         self.messages.lock().unwrap().clone()
     }
+
+    /// Returns the state shared by this agent and its tools.
+    pub fn state(&self) -> &Arc<dyn StateProvider> {
+        &self.state_provider
+    }
+}
+
+/// Everything needed to execute tool calls during a turn.
+struct ToolRuntime<'a, E> {
+    tools: &'a [Box<dyn Tool<E>>],
+    mcp_clients: &'a [McpClient],
+    hooks: &'a [Box<dyn Hook>],
+    state: &'a Arc<dyn StateProvider>,
+}
+
+impl<E> Clone for ToolRuntime<'_, E> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<E> Copy for ToolRuntime<'_, E> {}
+
+fn execute_tools<'a, E: std::fmt::Debug + Send>(
+    tool_uses: &'a [&'a ToolUseBlock],
+    runtime: ToolRuntime<'a, E>,
+    executor: ToolExecutor,
+) -> impl Stream<Item = AgentEvent> + Send + 'a {
+    let calls: Vec<_> = tool_uses
+        .iter()
+        .map(|tool_use| tool_call_events(tool_use, runtime))
+        .collect();
+
+    futures::stream::iter(calls).flatten_unordered(executor.max_concurrency())
+}
+
+fn tool_call_events<'a, E: std::fmt::Debug + Send>(
+    tool_use: &'a ToolUseBlock,
+    runtime: ToolRuntime<'a, E>,
+) -> Pin<Box<dyn Stream<Item = AgentEvent> + Send + 'a>> {
+    Box::pin(async_stream::stream! {
+        let mut input = tool_use.input.clone();
+        let mut decision = ToolCallDecision::Continue;
+        for hook in runtime.hooks {
+            decision = hook.before_tool_call(tool_use, &mut input).await;
+            if matches!(decision, ToolCallDecision::Veto(_)) {
+                break;
+            }
+        }
+        let tool_use = ToolUseBlock {
+            input,
+            ..tool_use.clone()
+        };
+
+        yield AgentEvent::ToolCallStarted {
+            tool_use: tool_use.clone(),
+        };
+
+        let start = Instant::now();
+        let mut content = match decision {
+            ToolCallDecision::Veto(result) => result,
+            ToolCallDecision::Continue => execute_tool(&tool_use, runtime).await,
+        };
+
+        for hook in runtime.hooks {
+            hook.after_tool_call(&tool_use, &mut content).await;
+        }
+
+        yield AgentEvent::ToolCallCompleted {
+            result: ToolResultBlock {
+                id: tool_use.id,
+                content,
+            },
+            duration: start.elapsed(),
+        };
+    })
+}
+
+/// Orders tool results to match the tool uses in the assistant message, since concurrently
+/// executed tools may complete in any order.
+fn ordered_tool_results(message: &Message, mut results: Vec<ToolResultBlock>) -> Vec<ContentBlock> {
+    let position = |id: &str| {
+        message
+            .content
+            .iter()
+            .position(|block| matches!(block, ContentBlock::ToolUse(tool_use) if tool_use.id == id))
+    };
+
+    results.sort_by_key(|result| position(&result.id));
+    results.into_iter().map(ContentBlock::ToolResult).collect()
+}
+
+async fn execute_tool<E: std::fmt::Debug>(
+    tool_use: &ToolUseBlock,
+    runtime: ToolRuntime<'_, E>,
+) -> ToolResult {
+    if let Some(tool) = runtime
+        .tools
+        .iter()
+        .find(|t| t.spec().name == tool_use.name)
+    {
+        invoke_tool(tool.as_ref(), tool_use, runtime.state).await
+    } else if let Some(client) = runtime
+        .mcp_clients
+        .iter()
+        .find(|c| c.has_tool(&tool_use.name))
+    {
+        invoke_mcp_tool(client, tool_use).await
+    } else {
+        Err(vec![ToolResultContent::Text(TextBlock(format!(
+            "Tool {} not found",
+            tool_use.name
+        )))])
+    }
+}
+
+const CANCELLED_REASON: &str = "turn was cancelled";
+
+const UNEXECUTED_REASON: &str = "the response ended before its tool calls could run";
+
+/// Sent to the model after a response was cut off by the max tokens limit.
+const CONTINUE_PROMPT: &str =
+    "Your response was cut off. Continue exactly where you left off without repeating yourself.";
+
+/// Name of the tool used to collect structured output from the model.
+const STRUCTURED_OUTPUT_TOOL: &str = "structured_output";
+
+/// A forced tool whose input is the final, validated answer of the turn.
+struct StructuredOutput {
+    spec: ToolSpec,
+    validate: fn(&serde_json::Value) -> Result<(), String>,
+    max_retries: usize,
+}
+
+fn validate_structured_output<T: DeserializeOwned>(
+    input: &serde_json::Value,
+) -> Result<(), String> {
+    T::deserialize(input).map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Collects the results for an assistant message's tool uses and guarantees that every tool use
+/// is answered, even if the turn is dropped before the tools finish.
+struct PendingToolResults {
+    conversation: Conversation,
+    message: Message,
+    results: Vec<ToolResultBlock>,
+    armed: bool,
+}
+
+impl PendingToolResults {
+    /// Appends the assistant `message` to the conversation and arms the guard in the same step,
+    /// so that no await point separates the tool uses from the promise to answer them. The caller
+    /// still has to report the message with [`Conversation::appended`].
+    fn append(conversation: &Conversation, message: Message) -> Self {
+        conversation.messages.lock().unwrap().push(message.clone());
+
+        Self {
+            conversation: conversation.clone(),
+            message,
+            results: Vec::new(),
+            armed: true,
+        }
+    }
+
+    /// Builds the tool result message, answering unanswered tool uses with `reason`. Returns
+    /// `None` when the assistant message has no tool uses.
+    fn finish(mut self, reason: impl std::fmt::Display) -> Option<Message> {
+        self.armed = false;
+        self.build(reason)
+    }
+
+    fn build(&mut self, reason: impl std::fmt::Display) -> Option<Message> {
+        let mut results = std::mem::take(&mut self.results);
+        for block in &self.message.content {
+            if let ContentBlock::ToolUse(tool_use) = block
+                && !results.iter().any(|result| result.id == tool_use.id)
+            {
+                results.push(skipped_tool_result(tool_use, &reason));
+            }
+        }
+
+        (!results.is_empty()).then(|| Message {
+            role: Role::User,
+            content: ordered_tool_results(&self.message, results),
+        })
+    }
+}
+
+impl Drop for PendingToolResults {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+
+        let Some(message) = self.build(CANCELLED_REASON) else {
+            return;
+        };
+        let Ok(mut messages) = self.conversation.messages.lock() else {
+            return;
+        };
+        messages.push(message.clone());
+        drop(messages);
+
+        // The results are in the conversation now, but reporting them has to wait for the
+        // runtime, as hooks and sessions are async.
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let conversation = self.conversation.clone();
+            runtime.spawn(async move {
+                if let Err(error) = conversation.appended(&message).await {
+                    tracing::warn!(error = %error, "failed to record cancelled tool results");
+                }
+            });
+        }
+    }
+}
+
+fn skipped_tool_result(tool_use: &ToolUseBlock, reason: impl std::fmt::Display) -> ToolResultBlock {
+    ToolResultBlock {
+        id: tool_use.id.clone(),
+        content: Err(vec![ToolResultContent::Text(TextBlock(format!(
+            "Tool {} was not executed: {reason}",
+            tool_use.name
+        )))]),
+    }
+}
+
+enum Interrupt {
+    Deadline,
+    Cancelled,
+}
+
+impl Interrupt {
+    /// The event that ends a turn interrupted this way.
+    fn event(self) -> AgentEvent {
+        match self {
+            Interrupt::Deadline => AgentEvent::LimitReached {
+                limit: TurnLimit::Deadline,
+            },
+            Interrupt::Cancelled => AgentEvent::Cancelled,
+        }
+    }
+}
+
+/// Polls the next item of a stream unless the deadline passes or the turn is cancelled first.
+async fn next_or_interrupt<S: Stream + Unpin>(
+    stream: &mut S,
+    deadline: Option<tokio::time::Instant>,
+    cancellation: &CancellationToken,
+) -> Result<Option<S::Item>, Interrupt> {
+    let next = async {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, stream.next())
+                .await
+                .map_err(|_| Interrupt::Deadline),
+            None => Ok(stream.next().await),
+        }
+    };
+
+    tokio::select! {
+        biased;
+        _ = cancellation.cancelled() => Err(Interrupt::Cancelled),
+        next = next => next,
+    }
+}
+
+/// Sleeps for `duration` unless the deadline passes or the turn is cancelled first.
+async fn sleep_or_interrupt(
+    duration: Duration,
+    deadline: Option<tokio::time::Instant>,
+    cancellation: &CancellationToken,
+) -> Result<(), Interrupt> {
+    let wake = tokio::time::Instant::now() + duration;
+    let (until, interrupt) = match deadline {
+        Some(deadline) if deadline <= wake => (deadline, Some(Interrupt::Deadline)),
+        _ => (wake, None),
+    };
+
+    tokio::select! {
+        biased;
+        _ = cancellation.cancelled() => Err(Interrupt::Cancelled),
+        _ = tokio::time::sleep_until(until) => interrupt.map_or(Ok(()), Err),
+    }
+}
+
+/// Whether a stream event carries part of the response, as opposed to framing or usage.
+fn is_content(event: &StreamEvent) -> bool {
+    !matches!(
+        event,
+        StreamEvent::MessageStart { .. }
+            | StreamEvent::Metadata { .. }
+            | StreamEvent::MessageComplete { .. }
+    )
+}
+
+/// Number of generated characters carried by a stream event.
+fn output_len(event: &StreamEvent) -> usize {
+    match event {
+        StreamEvent::TextDelta { delta, .. } | StreamEvent::ToolInputDelta { delta, .. } => {
+            delta.len()
+        }
+        StreamEvent::ReasoningDelta { text, .. } => text.as_ref().map_or(0, String::len),
+        _ => 0,
+    }
+}
+
+/// Appends a cache point to the system prompt unless it already ends with one.
+fn with_cache_point(system_prompt: SystemPrompt) -> SystemPrompt {
+    let mut blocks = match system_prompt {
+        SystemPrompt::Text(text) if text.is_empty() => return SystemPrompt::Text(text),
+        SystemPrompt::Text(text) => vec![SystemPromptBlock::Text(TextBlock(text))],
+        SystemPrompt::Structured(blocks) => blocks,
+    };
+
+    if !matches!(blocks.last(), None | Some(SystemPromptBlock::CachePoint(_))) {
+        blocks.push(SystemPromptBlock::CachePoint(CachePointBlock::Default));
+    }
+
+    SystemPrompt::Structured(blocks)
+}
+
+/// Returns the messages for a model call with a cache point ending the latest message. The cache
+/// point is not stored in the conversation, so breakpoints do not pile up across calls.
+/// Appends a continuation to the response it continues, joining the text split at the cut.
+fn merge_continuation(mut truncated: Message, continuation: Message) -> Message {
+    let mut content = continuation.content.into_iter().peekable();
+    if let (Some(ContentBlock::Text(TextBlock(text))), Some(ContentBlock::Text(TextBlock(more)))) =
+        (truncated.content.last_mut(), content.peek())
+    {
+        text.push_str(more);
+        content.next();
+    }
+    truncated.content.extend(content);
+    truncated
+}
+
+fn cache_latest_message(messages: &[Message]) -> Vec<Message> {
+    let mut messages = messages.to_vec();
+    if let Some(message) = messages.last_mut()
+        && !matches!(
+            message.content.last(),
+            None | Some(ContentBlock::CachePoint(_))
+        )
+    {
+        message
+            .content
+            .push(ContentBlock::CachePoint(CachePointBlock::Default));
+    }
+
+    messages
 }
 
-async fn execute_tools(
-    message: &Message,
-    _mcp_clients: &[McpClient],
-) -> Result<Vec<ContentBlock>, Box<dyn std::error::Error + Send + Sync>> {
-    let mut results = Vec::new();
+/// Rough token estimate used for output budgets, at about four characters per token.
+fn estimate_tokens(chars: usize) -> u64 {
+    chars.div_ceil(4) as u64
+}
+
+/// The conversation of a running turn. Messages appended through it are reported to the hooks
+/// and saved to the agent's session.
+#[derive(Clone)]
+struct Conversation {
+    messages: Arc<Mutex<Vec<Message>>>,
+    hooks: Arc<Vec<Box<dyn Hook>>>,
+    #[cfg(feature = "serde")]
+    state: Arc<dyn StateProvider>,
+    #[cfg(feature = "serde")]
+    session: Option<Arc<SessionRecorder>>,
+}
+
+impl Conversation {
+    async fn append(&self, message: Message) -> crate::Result<()> {
+        self.messages.lock().unwrap().push(message.clone());
+        self.appended(&message).await
+    }
+
+    /// Reports a message that was already pushed onto the conversation.
+    async fn appended(&self, message: &Message) -> crate::Result<()> {
+        for hook in self.hooks.iter() {
+            hook.message_appended(message).await;
+        }
 
-    for block in &message.content {
-        if let ContentBlock::ToolUse(tool_use) = block {
-            // TODO: Actually execute the tool via MCP
-            results.push(ContentBlock::ToolResult(ToolResultBlock {
-                id: tool_use.id.clone(),
-                content: Ok(vec![ToolResultContent::Text(TextBlock(format!(
-                    "Tool {} executed with input: {}",
-                    tool_use.name, tool_use.input
-                )))]),
-            }));
+        #[cfg(feature = "serde")]
+        if let Some(session) = &self.session {
+            session.record(&self.messages, self.state.as_ref()).await?;
         }
+
+        Ok(())
+    }
+}
+
+fn tool_input(tool_use: &ToolUseBlock) -> serde_json::Map<String, serde_json::Value> {
+    match &tool_use.input {
+        serde_json::Value::Object(input) => input.clone(),
+        _ => serde_json::Map::new(),
+    }
+}
+
+async fn invoke_tool<E: std::fmt::Debug>(
+    tool: &dyn Tool<E>,
+    tool_use: &ToolUseBlock,
+    state: &Arc<dyn StateProvider>,
+) -> ToolResult {
+    let input = tool_input(tool_use);
+    let context = ToolContext {
+        tool_use: tool_use.clone(),
+        state: Arc::clone(state),
+    };
+
+    match tool.invoke(&input, &context).await {
+        Ok(result) => result,
+        Err(e) => Err(vec![ToolResultContent::Text(TextBlock(format!(
+            "Tool {} failed: {e:?}",
+            tool_use.name
+        )))]),
     }
+}
 
-    Ok(results)
+async fn invoke_mcp_tool(client: &McpClient, tool_use: &ToolUseBlock) -> ToolResult {
+    match client.call_tool(&tool_use.name, tool_input(tool_use)).await {
+        Ok(result) => result,
+        Err(e) => Err(vec![ToolResultContent::Text(TextBlock(format!(
+            "Tool {} failed: {e}",
+            tool_use.name
+        )))]),
+    }
 }
@@ -0,0 +1,246 @@
+use futures::StreamExt;
+
+use crate::{
+    error::{Error, Result},
+    message::{ContentBlock, Message, Role, SystemPrompt, TextBlock},
+    model::model_provider::{ModelProvider, StreamArgs, StreamEvent, ToolPolicy},
+};
+
+/// What a conversation manager may use while reshaping the conversation.
+#[non_exhaustive]
+pub struct ConversationContext<'a> {
+    /// The agent's model provider. Calls made through it count towards the agent's metrics and
+    /// spend limit.
+    pub model_provider: &'a dyn ModelProvider,
+    /// The arguments of the upcoming model call.
+    pub args: &'a StreamArgs,
+}
+
+/// Keeps an agent's conversation within the model's context window.
+#[async_trait::async_trait]
+pub trait ConversationManager: Send + Sync {
+    /// Called before each model call.
+    async fn apply(
+        &self,
+        messages: &mut Vec<Message>,
+        context: &ConversationContext<'_>,
+    ) -> Result<()>;
+
+    /// Called when the model reports that its context window was exceeded.
+    ///
+    /// Returns `false` if the conversation could not be reduced any further.
+    async fn reduce(
+        &self,
+        messages: &mut Vec<Message>,
+        context: &ConversationContext<'_>,
+    ) -> Result<bool>;
+
+    fn boxed(self) -> Box<dyn ConversationManager>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+}
+
+/// Keeps only the most recent messages of the conversation.
+///
+/// The window always starts on a user message that is not a tool result, so a tool use is never
+/// separated from its result. A single prompt followed by a long tool loop is trimmed by keeping
+/// the prompt and dropping whole tool use and result pairs after it.
+#[derive(Clone, Debug)]
+pub struct SlidingWindowConversationManager {
+    /// Maximum number of messages to keep.
+    pub window_size: usize,
+}
+
+impl Default for SlidingWindowConversationManager {
+    fn default() -> Self {
+        Self { window_size: 40 }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConversationManager for SlidingWindowConversationManager {
+    async fn apply(
+        &self,
+        messages: &mut Vec<Message>,
+        _context: &ConversationContext<'_>,
+    ) -> Result<()> {
+        trim(messages, self.window_size);
+        Ok(())
+    }
+
+    async fn reduce(
+        &self,
+        messages: &mut Vec<Message>,
+        _context: &ConversationContext<'_>,
+    ) -> Result<bool> {
+        let keep = self.window_size.min(messages.len().saturating_sub(1));
+        Ok(trim(messages, keep))
+    }
+}
+
+/// Replaces older messages with a summary produced by the agent's model provider.
+#[derive(Clone, Debug)]
+pub struct SummarizingConversationManager {
+    /// Conversations with more messages than this are summarized before the next model call.
+    pub max_messages: usize,
+    /// Number of most recent messages that are kept verbatim.
+    pub preserve_recent_messages: usize,
+    /// System prompt for the summarization call.
+    pub summary_prompt: String,
+}
+
+impl Default for SummarizingConversationManager {
+    fn default() -> Self {
+        Self {
+            max_messages: 40,
+            preserve_recent_messages: 10,
+            summary_prompt: "Summarize the conversation so far. Keep every fact, decision, tool \
+                             result and open question needed to continue it."
+                .to_string(),
+        }
+    }
+}
+
+impl SummarizingConversationManager {
+    async fn summarize(
+        &self,
+        messages: &mut Vec<Message>,
+        context: &ConversationContext<'_>,
+    ) -> Result<bool> {
+        let earliest = messages
+            .len()
+            .saturating_sub(self.preserve_recent_messages)
+            .max(1);
+        let Some(split) = (earliest..messages.len())
+            .find(|&i| is_turn_start(&messages[i]) || is_pair_start(messages, i))
+        else {
+            return Ok(false);
+        };
+
+        let mut request = messages[..split].to_vec();
+        request.push(Message::new_user(
+            "Summarize the conversation above so it can be continued.",
+        ));
+
+        // The tool definitions are needed by providers that reject tool uses in the history
+        // without them, but the summary itself must not call tools.
+        let args = StreamArgs {
+            system_prompt: Some(SystemPrompt::new(self.summary_prompt.clone())),
+            tool_specs: context.args.tool_specs.clone(),
+            tool_policy: Some(ToolPolicy::None),
+            max_tokens: context.args.max_tokens,
+            ..Default::default()
+        };
+
+        let mut stream = context.model_provider.stream(&request, &args);
+        let mut summary = None;
+        while let Some(event) = stream.next().await {
+            if let StreamEvent::MessageComplete { message, .. } =
+                event.map_err(Error::from_model)?
+            {
+                summary = Some(message_text(&message));
+            }
+        }
+
+        let summary = summary.ok_or(Error::TruncatedStream)?;
+
+        let summary = format!("Summary of the earlier conversation:\n{summary}");
+        messages.drain(..split);
+        // Cut inside a tool loop, the conversation now starts on a tool use, which must follow a
+        // user message.
+        if is_turn_start(&messages[0]) {
+            messages[0]
+                .content
+                .insert(0, ContentBlock::Text(TextBlock(summary)));
+        } else {
+            messages.insert(0, Message::new_user(summary));
+        }
+
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl ConversationManager for SummarizingConversationManager {
+    async fn apply(
+        &self,
+        messages: &mut Vec<Message>,
+        context: &ConversationContext<'_>,
+    ) -> Result<()> {
+        if messages.len() > self.max_messages {
+            self.summarize(messages, context).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn reduce(
+        &self,
+        messages: &mut Vec<Message>,
+        context: &ConversationContext<'_>,
+    ) -> Result<bool> {
+        self.summarize(messages, context).await
+    }
+}
+
+/// Returns true if the conversation may start at this message.
+fn is_turn_start(message: &Message) -> bool {
+    matches!(message.role, Role::User)
+        && !message
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolResult(_)))
+}
+
+/// Returns true if the message at `index` is an assistant message with tool uses that follows a
+/// user message, so that dropping the messages before it keeps every tool use with its result.
+fn is_pair_start(messages: &[Message], index: usize) -> bool {
+    index > 0
+        && matches!(messages[index - 1].role, Role::User)
+        && matches!(messages[index].role, Role::Assistant)
+        && messages[index]
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse(_)))
+}
+
+/// Drops the oldest messages so at most `keep` remain, returning true if anything was dropped.
+fn trim(messages: &mut Vec<Message>, keep: usize) -> bool {
+    if messages.len() <= keep {
+        return false;
+    }
+
+    let earliest = messages.len() - keep;
+    if let Some(start) = (earliest..messages.len()).find(|&i| is_turn_start(&messages[i])) {
+        messages.drain(..start);
+        return true;
+    }
+
+    // Within a tool loop, the prompt that started it is kept and the oldest tool use and result
+    // pairs after it are dropped.
+    let Some(prompt) = (0..earliest).rev().find(|&i| is_turn_start(&messages[i])) else {
+        return false;
+    };
+    let Some(start) = (earliest + 1..messages.len()).find(|&i| is_pair_start(messages, i)) else {
+        return false;
+    };
+
+    messages.drain(prompt + 1..start);
+    messages.drain(..prompt);
+    true
+}
+
+fn message_text(message: &Message) -> String {
+    message
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::Text(TextBlock(text)) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
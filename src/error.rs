@@ -1,4 +1,4 @@
-use crate::mcp_client::McpError;
+use crate::{mcp_client::McpError, model::model_provider::ModelProviderError};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -12,4 +12,33 @@ pub enum Error {
     ToolExecution(String),
     #[error("MCP error: {0}")]
     McpError(#[from] McpError),
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("Model provider error: {0}")]
+    Model(ModelProviderError),
+    #[error("Model provider stream ended without a complete message")]
+    TruncatedStream,
+    #[error("The conversation exceeds the model's context window")]
+    ContextWindowOverflow,
+    #[error("Structured output error: {0}")]
+    StructuredOutput(String),
+    #[error("Invalid session id: {0:?}")]
+    InvalidSessionId(String),
+    #[error("Session not found: {0}")]
+    SessionNotFound(String),
+    #[error("Spend limit of ${limit:.2} exceeded with ${spent:.2} spent")]
+    SpendLimitExceeded { spent: f64, limit: f64 },
+    #[error("A spend limit requires pricing, but the model's pricing is unknown")]
+    SpendLimitWithoutPricing,
+}
+
+impl Error {
+    /// Recovers the crate error behind a boxed model provider error.
+    pub(crate) fn from_model(error: ModelProviderError) -> Self {
+        match error.downcast::<Error>() {
+            Ok(error) => *error,
+            Err(error) => Error::Model(error),
+        }
+    }
 }
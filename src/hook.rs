@@ -0,0 +1,50 @@
+use crate::{
+    message::{Message, StopReason, ToolResult, ToolUseBlock},
+    model::model_provider::StreamArgs,
+};
+
+/// What the agent should do with a tool call after the `before_tool_call` hooks have run.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub enum ToolCallDecision {
+    /// Invoke the tool with the (possibly rewritten) input.
+    #[default]
+    Continue,
+    /// Skip the tool and report the given result to the model instead.
+    Veto(ToolResult),
+}
+
+/// Callbacks invoked by an agent at well-defined points of a turn.
+///
+/// Every method has a no-op default so implementors only override what they need. Hooks run in
+/// the order they were registered.
+#[async_trait::async_trait]
+pub trait Hook: Send + Sync {
+    /// Called before each model call. The stream arguments may be modified.
+    async fn before_model_call(&self, _messages: &[Message], _args: &mut StreamArgs) {}
+
+    /// Called once the model has produced a complete message.
+    async fn after_model_call(&self, _message: &Message, _stop_reason: &StopReason) {}
+
+    /// Called before a tool is invoked. The tool input may be rewritten, or the call vetoed.
+    async fn before_tool_call(
+        &self,
+        _tool_use: &ToolUseBlock,
+        _input: &mut serde_json::Value,
+    ) -> ToolCallDecision {
+        ToolCallDecision::Continue
+    }
+
+    /// Called after a tool has produced a result. The result may be replaced.
+    async fn after_tool_call(&self, _tool_use: &ToolUseBlock, _result: &mut ToolResult) {}
+
+    /// Called whenever a message is appended to the conversation.
+    async fn message_appended(&self, _message: &Message) {}
+
+    fn boxed(self) -> Box<dyn Hook>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+}
@@ -1,7 +1,13 @@
 pub mod agent;
+pub mod conversation_manager;
 mod error;
+pub mod hook;
 pub mod mcp_client;
 pub mod message;
 pub mod model;
+#[cfg(feature = "serde")]
+pub mod session;
 pub mod state_provider;
 pub mod tool;
+
+pub use error::{Error, Result};
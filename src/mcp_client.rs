@@ -1,10 +1,17 @@
+use base64::Engine;
 use rmcp::ServiceExt;
+use rmcp::model::{CallToolRequestParam, CallToolResult, Content, RawContent, ResourceContents};
 use rmcp::service::RunningService;
 use rmcp::transport::StreamableHttpClientTransport;
+use rmcp::transport::streamable_http_client::StreamableHttpClientTransportConfig;
 use rmcp::{RoleClient, transport::TokioChildProcess};
 use tokio::process::Command;
 
 use crate::error::Result;
+use crate::message::{
+    DocumentBlock, DocumentFormat, DocumentSource, ImageBlock, ImageFormat, ImageSource, JsonBlock,
+    TextBlock, ToolResult, ToolResultContent,
+};
 use crate::tool::ToolSpec;
 
 #[derive(Debug, thiserror::Error)]
@@ -34,6 +41,7 @@ pub enum TransportArgs {
     },
     StreamableHttp {
         url: String,
+        /// Sent as a bearer token with every request.
         api_key: Option<String>,
     },
 }
@@ -62,12 +70,22 @@ impl McpClient {
                     .map_err(McpError::from)?
             }
             TransportArgs::StreamableHttp { url, api_key } => {
-                ().serve(StreamableHttpClientTransport::from_uri(url))
+                let mut config = StreamableHttpClientTransportConfig::with_uri(url);
+                config.auth_header = api_key;
+                ().serve(StreamableHttpClientTransport::from_config(config))
                     .await
                     .map_err(McpError::from)?
             }
         };
 
+        Self::from_service(args.name, args.version, service).await
+    }
+
+    async fn from_service(
+        name: String,
+        version: String,
+        service: RunningService<RoleClient, ()>,
+    ) -> Result<Self> {
         let tool_specs = service
             .list_all_tools()
             .await
@@ -77,8 +95,8 @@ impl McpClient {
             .collect();
 
         Ok(Self {
-            name: args.name,
-            version: args.version,
+            name,
+            version,
             service,
             tool_specs,
         })
@@ -87,6 +105,117 @@ impl McpClient {
     pub fn tool_specs(&self) -> &[ToolSpec] {
         &self.tool_specs
     }
+
+    /// Returns true if this client's server provides a tool with the given name.
+    pub fn has_tool(&self, name: &str) -> bool {
+        self.tool_specs.iter().any(|spec| spec.name == name)
+    }
+
+    /// Calls a tool on the MCP server and converts its output into a [`ToolResult`].
+    pub async fn call_tool(
+        &self,
+        name: &str,
+        input: serde_json::Map<String, serde_json::Value>,
+    ) -> Result<ToolResult> {
+        let result = self
+            .service
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: Some(input),
+            })
+            .await
+            .map_err(McpError::from)?;
+
+        Ok(tool_result_from_mcp(result))
+    }
+}
+
+fn tool_result_from_mcp(result: CallToolResult) -> ToolResult {
+    let mut content: Vec<ToolResultContent> = result
+        .content
+        .into_iter()
+        .map(tool_result_content_from_mcp)
+        .collect();
+
+    // Servers returning structured content are expected to repeat it as text for older clients,
+    // so it is only used when there is nothing else.
+    if content.is_empty()
+        && let Some(structured) = result.structured_content
+    {
+        content.push(ToolResultContent::Json(JsonBlock(structured)));
+    }
+
+    match result.is_error {
+        Some(true) => Err(content),
+        _ => Ok(content),
+    }
+}
+
+fn tool_result_content_from_mcp(content: Content) -> ToolResultContent {
+    match content.raw {
+        RawContent::Text(text) => ToolResultContent::Text(TextBlock(text.text)),
+        RawContent::Image(image) => {
+            decoded_image(&image.data, &image.mime_type).unwrap_or_else(|| {
+                ToolResultContent::Text(TextBlock(format!(
+                    "[unsupported image of type {}]",
+                    image.mime_type
+                )))
+            })
+        }
+        RawContent::Resource(resource) => match resource.resource {
+            ResourceContents::TextResourceContents { text, .. } => {
+                ToolResultContent::Text(TextBlock(text))
+            }
+            ResourceContents::BlobResourceContents {
+                uri,
+                mime_type,
+                blob,
+                ..
+            } => mime_type
+                .as_deref()
+                .and_then(|mime_type| {
+                    decoded_image(&blob, mime_type)
+                        .or_else(|| decoded_document(&uri, &blob, mime_type))
+                })
+                .unwrap_or_else(|| {
+                    ToolResultContent::Text(TextBlock(format!("[unsupported resource {uri}]")))
+                }),
+        },
+        RawContent::ResourceLink(resource) => {
+            ToolResultContent::Text(TextBlock(format!("[resource {}]", resource.uri)))
+        }
+        RawContent::Audio(audio) => ToolResultContent::Text(TextBlock(format!(
+            "[unsupported audio of type {}]",
+            audio.mime_type
+        ))),
+    }
+}
+
+fn decoded_image(data: &str, mime_type: &str) -> Option<ToolResultContent> {
+    let format = ImageFormat::from_mime_type(mime_type)?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .ok()?;
+
+    Some(ToolResultContent::Image(ImageBlock {
+        format,
+        source: ImageSource::Bytes(bytes),
+    }))
+}
+
+fn decoded_document(uri: &str, data: &str, mime_type: &str) -> Option<ToolResultContent> {
+    let format = DocumentFormat::from_mime_type(mime_type)?;
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data)
+        .ok()?;
+
+    Some(ToolResultContent::Document(DocumentBlock {
+        name: uri.to_string(),
+        format,
+        source: DocumentSource::Bytes(bytes),
+        citations: false,
+        context: None,
+    }))
 }
 
 impl std::fmt::Debug for McpClient {
@@ -98,3 +227,160 @@ impl std::fmt::Debug for McpClient {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rmcp::{
+        ErrorData, RoleServer, ServerHandler,
+        model::{
+            CallToolRequestParam, ListToolsResult, PaginatedRequestParam, RawResource,
+            ServerCapabilities, ServerInfo, Tool,
+        },
+        service::RequestContext,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    /// Serves an `echo` tool that answers with the name and arguments it was called with.
+    struct EchoServer;
+
+    impl ServerHandler for EchoServer {
+        fn get_info(&self) -> ServerInfo {
+            ServerInfo {
+                capabilities: ServerCapabilities::builder().enable_tools().build(),
+                ..Default::default()
+            }
+        }
+
+        async fn list_tools(
+            &self,
+            _request: Option<PaginatedRequestParam>,
+            _context: RequestContext<RoleServer>,
+        ) -> std::result::Result<ListToolsResult, ErrorData> {
+            Ok(ListToolsResult::with_all_items(vec![Tool::new(
+                "echo",
+                "Echoes its arguments",
+                Arc::new(serde_json::Map::new()),
+            )]))
+        }
+
+        async fn call_tool(
+            &self,
+            request: CallToolRequestParam,
+            _context: RequestContext<RoleServer>,
+        ) -> std::result::Result<CallToolResult, ErrorData> {
+            let arguments = serde_json::Value::Object(request.arguments.unwrap_or_default());
+            Ok(CallToolResult::success(vec![Content::text(format!(
+                "{} {arguments}",
+                request.name
+            ))]))
+        }
+    }
+
+    async fn connect() -> McpClient {
+        let (client, server) = tokio::io::duplex(4096);
+        tokio::spawn(async move {
+            let service = EchoServer.serve(server).await.unwrap();
+            service.waiting().await.unwrap();
+        });
+
+        let service = ().serve(client).await.unwrap();
+        McpClient::from_service("test".into(), "1.0".into(), service)
+            .await
+            .unwrap()
+    }
+
+    fn text(text: &str) -> ToolResultContent {
+        ToolResultContent::Text(TextBlock(text.into()))
+    }
+
+    /// Message content has no `PartialEq`, so results are compared by their debug output.
+    fn assert_same(actual: ToolResult, expected: ToolResult) {
+        assert_eq!(format!("{actual:?}"), format!("{expected:?}"));
+    }
+
+    #[tokio::test]
+    async fn dispatches_tool_calls_to_the_server() {
+        let client = connect().await;
+
+        assert!(client.has_tool("echo"));
+        assert!(!client.has_tool("missing"));
+        assert_eq!(client.tool_specs()[0].name, "echo");
+
+        let input = json!({ "city": "Oslo" }).as_object().unwrap().clone();
+        let result = client.call_tool("echo", input).await.unwrap();
+        assert_same(result, Ok(vec![text(r#"echo {"city":"Oslo"}"#)]));
+    }
+
+    #[test]
+    fn converts_tool_result_content() {
+        let png = base64::engine::general_purpose::STANDARD.encode(b"png");
+        let pdf = base64::engine::general_purpose::STANDARD.encode(b"pdf");
+
+        let result = CallToolResult {
+            content: vec![
+                Content::text("hello"),
+                Content::image(png, "image/png"),
+                Content::embedded_text("file:///notes.txt", "notes"),
+                Content::resource(ResourceContents::BlobResourceContents {
+                    uri: "file:///report.pdf".into(),
+                    mime_type: Some("application/pdf".into()),
+                    blob: pdf,
+                    meta: None,
+                }),
+                Content::resource_link(RawResource::new("file:///big.bin", "big")),
+                Content::image("not base64", "image/png"),
+            ],
+            structured_content: Some(json!({ "ok": true })),
+            is_error: None,
+            meta: None,
+        };
+
+        assert_same(
+            tool_result_from_mcp(result),
+            Ok(vec![
+                text("hello"),
+                ToolResultContent::Image(ImageBlock {
+                    format: ImageFormat::Png,
+                    source: ImageSource::Bytes(b"png".to_vec()),
+                }),
+                text("notes"),
+                ToolResultContent::Document(DocumentBlock {
+                    name: "file:///report.pdf".into(),
+                    format: DocumentFormat::Pdf,
+                    source: DocumentSource::Bytes(b"pdf".to_vec()),
+                    citations: false,
+                    context: None,
+                }),
+                text("[resource file:///big.bin]"),
+                text("[unsupported image of type image/png]"),
+            ]),
+        );
+    }
+
+    #[test]
+    fn falls_back_to_structured_content() {
+        let result = CallToolResult {
+            content: Vec::new(),
+            structured_content: Some(json!({ "ok": true })),
+            is_error: None,
+            meta: None,
+        };
+
+        assert_same(
+            tool_result_from_mcp(result),
+            Ok(vec![ToolResultContent::Json(JsonBlock(
+                json!({ "ok": true }),
+            ))]),
+        );
+    }
+
+    #[test]
+    fn maps_error_results_to_errors() {
+        let result = CallToolResult::error(vec![Content::text("boom")]);
+        assert_same(tool_result_from_mcp(result), Err(vec![text("boom")]));
+    }
+}
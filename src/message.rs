@@ -1,3 +1,31 @@
+//! Conversation messages and their content blocks.
+//!
+//! # Wire format
+//!
+//! With the `serde` feature enabled, every type in this crate serializes to a stable JSON format:
+//!
+//! - Enums that carry data are adjacently tagged as `{"type": "<variant>", "value": <payload>}`,
+//!   with the payload omitted for variants that have none.
+//! - Enums without data serialize as plain strings.
+//! - Variant names are `snake_case`, so `ContentBlock::ToolUse` is tagged `"tool_use"`.
+//! - Binary data, such as [`ImageSource::Bytes`] or [`ReasoningBlock::redacted`], is encoded as
+//!   standard base64 with padding.
+//! - A [`ToolResultBlock`] stores its outcome as `"status": "success"` or `"status": "error"`
+//!   next to its `"content"`.
+//!
+//! ```json
+//! {
+//!   "role": "user",
+//!   "content": [
+//!     { "type": "text", "value": "Describe this image." },
+//!     {
+//!       "type": "image",
+//!       "value": { "format": "png", "source": { "type": "bytes", "value": "iVBORw0KGgo=" } }
+//!     }
+//!   ]
+//! }
+//! ```
+
 use std::fmt::Display;
 
 #[cfg(feature = "serde")]
@@ -5,6 +33,7 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive] // Play it safe unless someone complains.
 pub enum Role {
     User,
@@ -41,9 +70,16 @@ pub struct ToolUseBlock {
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
+#[non_exhaustive]
 pub enum ToolResultContent {
     Text(TextBlock),
     Json(JsonBlock),
+    Image(ImageBlock),
+    Document(DocumentBlock),
 }
 
 #[derive(Clone, Debug)]
@@ -52,6 +88,7 @@ pub struct ToolResultBlock {
     /// The id of the tool use this result corresponds to.
     pub id: String,
     /// The content returned by the tool.
+    #[cfg_attr(feature = "serde", serde(flatten, with = "tool_result_status"))]
     pub content: Result<Vec<ToolResultContent>, Vec<ToolResultContent>>,
 }
 
@@ -65,11 +102,13 @@ pub struct ReasoningBlock {
     /// A cryptographic signature for verification purposes.
     pub signature: String,
     /// The redacted content of the reasoning process.
+    #[cfg_attr(feature = "serde", serde(with = "base64_bytes"))]
     pub redacted: Vec<u8>,
 }
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum CachePointBlock {
     Default,
@@ -78,6 +117,7 @@ pub enum CachePointBlock {
 /// Image format for image content blocks.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum ImageFormat {
     Png,
@@ -86,11 +126,39 @@ pub enum ImageFormat {
     Webp,
 }
 
+impl ImageFormat {
+    /// Returns the format matching a MIME type such as `image/png`.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "image/png" => Some(ImageFormat::Png),
+            "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+            "image/gif" => Some(ImageFormat::Gif),
+            "image/webp" => Some(ImageFormat::Webp),
+            _ => None,
+        }
+    }
+
+    /// Returns the MIME type for this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
 /// Source of image data.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum ImageSource {
+    #[cfg_attr(feature = "serde", serde(with = "base64_bytes"))]
     Bytes(Vec<u8>),
     Url(String),
 }
@@ -107,6 +175,7 @@ pub struct ImageBlock {
 /// Video format for video content blocks.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum VideoFormat {
     Mkv,
@@ -116,13 +185,35 @@ pub enum VideoFormat {
     Flv,
     Mpeg,
     Wmv,
+    #[cfg_attr(feature = "serde", serde(rename = "3gp"))]
     Tgp, // 3gp
 }
 
+impl VideoFormat {
+    /// Returns the MIME type for this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            VideoFormat::Mkv => "video/x-matroska",
+            VideoFormat::Mov => "video/quicktime",
+            VideoFormat::Mp4 => "video/mp4",
+            VideoFormat::Webm => "video/webm",
+            VideoFormat::Flv => "video/x-flv",
+            VideoFormat::Mpeg => "video/mpeg",
+            VideoFormat::Wmv => "video/x-ms-wmv",
+            VideoFormat::Tgp => "video/3gpp",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum VideoSource {
+    #[cfg_attr(feature = "serde", serde(with = "base64_bytes"))]
     Bytes(Vec<u8>),
 }
 
@@ -138,6 +229,7 @@ pub struct VideoBlock {
 /// Document format for document content blocks.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum DocumentFormat {
     Pdf,
@@ -153,11 +245,61 @@ pub enum DocumentFormat {
     Xml,
 }
 
+impl DocumentFormat {
+    /// Returns the format matching a MIME type such as `application/pdf`.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "application/pdf" => Some(DocumentFormat::Pdf),
+            "text/csv" => Some(DocumentFormat::Csv),
+            "application/msword" => Some(DocumentFormat::Doc),
+            "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+                Some(DocumentFormat::Docx)
+            }
+            "application/vnd.ms-excel" => Some(DocumentFormat::Xls),
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet" => {
+                Some(DocumentFormat::Xlsx)
+            }
+            "text/html" => Some(DocumentFormat::Html),
+            "text/plain" => Some(DocumentFormat::Txt),
+            "text/markdown" => Some(DocumentFormat::Md),
+            "application/json" => Some(DocumentFormat::Json),
+            "application/xml" | "text/xml" => Some(DocumentFormat::Xml),
+            _ => None,
+        }
+    }
+
+    /// Returns the MIME type for this format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            DocumentFormat::Pdf => "application/pdf",
+            DocumentFormat::Csv => "text/csv",
+            DocumentFormat::Doc => "application/msword",
+            DocumentFormat::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            DocumentFormat::Xls => "application/vnd.ms-excel",
+            DocumentFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            DocumentFormat::Html => "text/html",
+            DocumentFormat::Txt => "text/plain",
+            DocumentFormat::Md => "text/markdown",
+            DocumentFormat::Json => "application/json",
+            DocumentFormat::Xml => "application/xml",
+        }
+    }
+}
+
 /// Source of document data.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum DocumentSource {
+    #[cfg_attr(feature = "serde", serde(with = "base64_bytes"))]
     Bytes(Vec<u8>),
     Text(String),
     Structured(Vec<TextBlock>), // TODO: This looks like poor data modeling.
@@ -184,6 +326,7 @@ pub struct DocumentBlock {
 /// Qualifier for guard content blocks.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum GuardQualifier {
     GroundingSource,
@@ -203,6 +346,7 @@ pub struct GuardText {
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum GuardImageFormat {
     Png,
@@ -216,11 +360,16 @@ pub struct GuardImage {
     /// The format of the image.
     pub format: GuardImageFormat,
     /// The image source.
+    #[cfg_attr(feature = "serde", serde(with = "base64_bytes"))]
     pub source: Vec<u8>,
 }
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum GuardBlock {
     Text(GuardText),
@@ -229,6 +378,10 @@ pub enum GuardBlock {
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum ContentBlock {
     Text(TextBlock),
@@ -245,6 +398,7 @@ pub enum ContentBlock {
 /// Reason for stopping generation.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
 #[non_exhaustive]
 pub enum StopReason {
     /// Content was filtered by safety mechanisms.
@@ -266,6 +420,10 @@ pub enum StopReason {
 /// Content block for system prompts.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum SystemPromptBlock {
     Text(TextBlock),
@@ -276,6 +434,10 @@ pub enum SystemPromptBlock {
 /// The system prompt supplied to the model provider.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum SystemPrompt {
     Text(String),
@@ -305,3 +467,88 @@ impl Message {
         }
     }
 }
+
+/// Serializes bytes as a base64 string.
+#[cfg(feature = "serde")]
+pub(crate) mod base64_bytes {
+    use base64::{Engine, engine::general_purpose::STANDARD};
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(D::Error::custom)
+    }
+
+    /// Serializes optional bytes as a base64 string or `null`.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        pub fn serialize<S: Serializer>(
+            bytes: &Option<Vec<u8>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match bytes {
+                Some(bytes) => super::serialize(bytes, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Vec<u8>>, D::Error> {
+            #[derive(Deserialize)]
+            struct Bytes(#[serde(with = "super")] Vec<u8>);
+
+            Ok(Option::<Bytes>::deserialize(deserializer)?.map(|Bytes(bytes)| bytes))
+        }
+    }
+}
+
+/// Serializes a [`ToolResult`] as a `status` and the `content` it carries.
+#[cfg(feature = "serde")]
+mod tool_result_status {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::{ToolResult, ToolResultContent};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Status {
+        Success,
+        Error,
+    }
+
+    #[derive(Serialize)]
+    struct Borrowed<'a> {
+        status: Status,
+        content: &'a [ToolResultContent],
+    }
+
+    #[derive(Deserialize)]
+    struct Owned {
+        status: Status,
+        content: Vec<ToolResultContent>,
+    }
+
+    pub fn serialize<S: Serializer>(result: &ToolResult, serializer: S) -> Result<S::Ok, S::Error> {
+        let (status, content) = match result {
+            Ok(content) => (Status::Success, content),
+            Err(content) => (Status::Error, content),
+        };
+
+        Borrowed { status, content }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<ToolResult, D::Error> {
+        let Owned { status, content } = Owned::deserialize(deserializer)?;
+
+        Ok(match status {
+            Status::Success => Ok(content),
+            Status::Error => Err(content),
+        })
+    }
+}
@@ -1,10 +1,17 @@
+use std::time::{Duration, Instant};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
 use crate::{
+    error::Error,
     message::{
-        ContentBlock, Message, Role, StopReason, SystemPrompt, SystemPromptBlock, TextBlock,
+        ContentBlock, DocumentBlock, DocumentFormat, DocumentSource, ImageBlock, ImageSource,
+        Message, ReasoningBlock, Role, StopReason, SystemPrompt, SystemPromptBlock, TextBlock,
         ToolResultContent, ToolUseBlock,
     },
     model::model_provider::{
-        ModelProvider, ModelProviderStream, StreamArgs, StreamEvent, ToolPolicy,
+        ModelPricing, ModelProvider, ModelProviderError, ModelProviderStream, StreamArgs,
+        StreamEvent, ToolPolicy, Usage,
     },
     tool::ToolSpec,
 };
@@ -13,18 +20,100 @@ pub use anthropoki::ApiVersion;
 pub use anthropoki::Model;
 
 use anthropoki::{
-    AnthropicClient, Content as AnthropicContent, ContentBlock as AnthropicContentBlock,
-    ContentBlockDelta, InputMessage as AnthropicInputMessage, MessagesRequest, MessagesRequestBody,
+    AnthropicClient, CacheControl as AnthropicCacheControl, Citations as AnthropicCitations,
+    Content as AnthropicContent, ContentBlock as AnthropicContentBlock, ContentBlockDelta,
+    DocumentSource as AnthropicDocumentSource, ImageSource as AnthropicImageSource,
+    InputMessage as AnthropicInputMessage, MessagesRequest, MessagesRequestBody,
     MessagesResponseEvent, Role as AnthropicRole, StopReason as AnthropicStopReason,
-    Tool as AnthropicTool, ToolChoice as AnthropicToolChoice,
+    Thinking as AnthropicThinking, Tool as AnthropicTool, ToolChoice as AnthropicToolChoice,
     ToolResultContentBlock as AnthropicToolResultContentBlock,
 };
 
+/// Errors raised while translating messages for the Anthropic API.
+#[derive(thiserror::Error, Debug)]
+pub enum AnthropicError {
+    #[error("Unsupported content for the Anthropic API: {0}")]
+    UnsupportedContent(String),
+}
+
+/// Prices for Claude models, keyed by model.
+///
+/// The default table holds Anthropic's list prices. Entries can be added for custom models or
+/// replaced to reflect negotiated rates.
+#[derive(Clone, Debug)]
+pub struct PricingTable {
+    prices: Vec<(Model, ModelPricing)>,
+}
+
+impl PricingTable {
+    /// Creates a table without any prices.
+    pub fn empty() -> Self {
+        Self { prices: Vec::new() }
+    }
+
+    /// Sets the prices for `model`, replacing any existing entry.
+    pub fn with_pricing(mut self, model: Model, pricing: ModelPricing) -> Self {
+        self.prices.retain(|(m, _)| *m != model);
+        self.prices.push((model, pricing));
+        self
+    }
+
+    pub fn get(&self, model: Model) -> Option<ModelPricing> {
+        self.prices
+            .iter()
+            .find(|(m, _)| *m == model)
+            .map(|(_, pricing)| *pricing)
+    }
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let opus = ModelPricing {
+            input: 15.0,
+            output: 75.0,
+            cache_read: 1.5,
+            cache_write: 18.75,
+        };
+        let sonnet = ModelPricing {
+            input: 3.0,
+            output: 15.0,
+            cache_read: 0.3,
+            cache_write: 3.75,
+        };
+
+        Self::empty()
+            .with_pricing(Model::ClaudeOpus4_1, opus)
+            .with_pricing(Model::ClaudeOpus4, opus)
+            .with_pricing(Model::ClaudeSonnet4_5, sonnet)
+            .with_pricing(Model::ClaudeSonnet4, sonnet)
+            .with_pricing(
+                Model::ClaudeHaiku4_5,
+                ModelPricing {
+                    input: 1.0,
+                    output: 5.0,
+                    cache_read: 0.1,
+                    cache_write: 1.25,
+                },
+            )
+            .with_pricing(
+                Model::ClaudeHaiku3_5,
+                ModelPricing {
+                    input: 0.8,
+                    output: 4.0,
+                    cache_read: 0.08,
+                    cache_write: 1.0,
+                },
+            )
+    }
+}
+
 #[derive(Debug)]
 pub struct AnthropicModelProvider {
     api_version: ApiVersion,
     api_key: String,
     model: Model,
+    thinking_budget: Option<u32>,
+    pricing: PricingTable,
     client: AnthropicClient,
 }
 
@@ -36,36 +125,84 @@ impl AnthropicModelProvider {
             api_version,
             api_key,
             model,
+            thinking_budget: None,
+            pricing: PricingTable::default(),
             client,
         }
     }
 
-    fn build_request(&self, messages: &[Message], args: &StreamArgs) -> MessagesRequest<'static> {
-        MessagesRequest {
+    /// Looks up the model's prices in `pricing` instead of the list prices.
+    pub fn with_pricing_table(mut self, pricing: PricingTable) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Enables extended thinking, letting the model spend up to `budget_tokens` on reasoning
+    /// before it answers.
+    ///
+    /// The budget counts towards `max_tokens`, which must be larger. Anthropic rejects thinking
+    /// combined with a temperature or with a policy that forces tool use.
+    pub fn with_thinking_budget(mut self, budget_tokens: u32) -> Self {
+        self.thinking_budget = Some(budget_tokens);
+        self
+    }
+
+    fn build_request(
+        &self,
+        messages: &[Message],
+        args: &StreamArgs,
+    ) -> Result<MessagesRequest<'static>, AnthropicError> {
+        let mut system = match &args.system_prompt {
+            Some(SystemPrompt::Structured(blocks)) => {
+                Some(AnthropicContent::Blocks(system_blocks(blocks)?))
+            }
+            Some(prompt) => Some(String::from(prompt).into()),
+            None => None,
+        };
+        let mut messages = messages
+            .iter()
+            .map(AnthropicInputMessage::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tools = args.tool_specs.as_ref().map(|specs| {
+            let mut tools: Vec<AnthropicTool> = specs.iter().map(|s| s.into()).collect();
+            // A breakpoint on the last tool caches every tool definition.
+            if let (Some(_), Some(tool)) = (&args.tool_cache_point, tools.last_mut()) {
+                tool.cache_control = Some(AnthropicCacheControl::Ephemeral);
+            }
+            tools
+        });
+        limit_cache_breakpoints(tools.as_mut(), system.as_mut(), &mut messages);
+
+        Ok(MessagesRequest {
             anthropic_beta: None,
             anthropic_version: self.api_version,
             x_api_key: self.api_key.clone().into(),
             body: MessagesRequestBody {
                 model: self.model,
-                messages: messages.iter().map(|m| m.into()).collect(),
+                messages,
                 max_tokens: args.max_tokens.unwrap_or(4096),
                 stream: true,
-                system: args.system_prompt.as_ref().map(|s| String::from(s).into()),
+                system,
                 temperature: args.temperature,
                 top_p: args.top_p,
                 stop_sequences: args
                     .stop_sequences
                     .as_ref()
                     .map(|s| s.iter().map(|seq| String::from(seq).into()).collect()),
-                tool_choice: args.tool_policy.as_ref().map(|p| p.into()),
-                tools: args
+                // Anthropic rejects a tool choice in a request without tools.
+                tool_choice: args
                     .tool_specs
-                    .as_ref()
-                    .map(|specs| specs.iter().map(|s| s.into()).collect()),
+                    .is_some()
+                    .then(|| tool_choice(args))
+                    .flatten(),
+                tools,
+                thinking: self
+                    .thinking_budget
+                    .map(|budget_tokens| AnthropicThinking::Enabled { budget_tokens }),
                 ..Default::default()
             },
             ..Default::default()
-        }
+        })
     }
 }
 
@@ -75,86 +212,229 @@ impl ModelProvider for AnthropicModelProvider {
         let client = self.client.clone();
 
         Box::pin(async_stream::try_stream! {
-            let mut stream = client.messages_stream(&request).await?;
-
-            let mut current_role = Role::Assistant;
-            let mut content_blocks: Vec<ContentBlock> = vec![];
-            let mut current_block_text = String::new();
-            let mut current_tool_id = String::new();
-            let mut current_tool_name = String::new();
-            let mut current_tool_input = String::new();
-            let mut stop_reason = StopReason::EndTurn;
-
-            while let Some(event) = stream.recv().await? {
-                match event {
-                    MessagesResponseEvent::Ping => {},
-                    MessagesResponseEvent::MessageStart { message } => {
-                        current_role = message.role.into();
-                        yield StreamEvent::MessageStart { role: current_role.clone() };
-                    },
-                    MessagesResponseEvent::ContentBlockStart { index, content_block } => {
-                        match content_block {
-                            AnthropicContentBlock::Text { .. } => {
-                                current_block_text.clear();
-                                yield StreamEvent::TextStart { index };
-                            }
-                            AnthropicContentBlock::ToolUse { id, name, .. } => {
-                                current_tool_id = id.clone();
-                                current_tool_name = name.clone();
-                                current_tool_input.clear();
-                                yield StreamEvent::ToolUseStart { index, id, name };
-                            }
-                            _ => {}
-                        }
-                    },
-                    MessagesResponseEvent::ContentBlockDelta { index, delta } => {
-                        match delta {
-                            ContentBlockDelta::TextDelta { text } => {
-                                current_block_text.push_str(&text);
-                                yield StreamEvent::TextDelta { index, delta: text };
-                            }
-                            ContentBlockDelta::InputJsonDelta { partial_json } => {
-                                current_tool_input.push_str(&partial_json);
-                                yield StreamEvent::ToolInputDelta { index, delta: partial_json };
-                            }
-                        }
-                    },
-                    MessagesResponseEvent::ContentBlockStop { index } => {
-                        let block = if !current_tool_id.is_empty() {
-                            let input: serde_json::Value = serde_json::from_str(&current_tool_input)
-                                .unwrap_or(serde_json::Value::Null);
-                            let block = ContentBlock::ToolUse(ToolUseBlock {
-                                id: std::mem::take(&mut current_tool_id),
-                                name: std::mem::take(&mut current_tool_name),
-                                input,
-                            });
-                            current_tool_input.clear();
-                            block
-                        } else {
-                            ContentBlock::Text(TextBlock(std::mem::take(&mut current_block_text)))
-                        };
-
-                        content_blocks.push(block.clone());
-
-                        yield StreamEvent::ContentBlockComplete { index, block };
-                    },
-                    MessagesResponseEvent::MessageDelta { delta } => {
-                        if let Some(reason) = delta.stop_reason {
-                            stop_reason = reason.into();
-                        }
-                    },
-                    MessagesResponseEvent::MessageStop => {
-                        let message = Message {
-                            role: current_role.clone(),
-                            content: std::mem::take(&mut content_blocks),
-                        };
-
-                        yield StreamEvent::MessageComplete { message, stop_reason: stop_reason.clone() };
-                    }
+            let request = request?;
+            let started = Instant::now();
+            let mut stream = client.messages_stream(&request).await.map_err(api_error)?;
+            let mut state = MessageStreamState::default();
+
+            while let Some(event) = stream.recv().await.map_err(api_error)? {
+                for event in state.process(event, started.elapsed())? {
+                    yield event;
                 }
             }
         })
     }
+
+    fn pricing(&self) -> Option<ModelPricing> {
+        self.pricing.get(self.model)
+    }
+}
+
+/// Turns Messages API events into stream events.
+struct MessageStreamState {
+    role: Role,
+    content: Vec<ContentBlock>,
+    text: String,
+    tool_id: String,
+    tool_name: String,
+    tool_input: String,
+    reasoning: Option<ReasoningBlock>,
+    stop_reason: StopReason,
+    usage: Usage,
+}
+
+impl Default for MessageStreamState {
+    fn default() -> Self {
+        Self {
+            role: Role::Assistant,
+            content: Vec::new(),
+            text: String::new(),
+            tool_id: String::new(),
+            tool_name: String::new(),
+            tool_input: String::new(),
+            reasoning: None,
+            stop_reason: StopReason::EndTurn,
+            usage: Usage::default(),
+        }
+    }
+}
+
+impl MessageStreamState {
+    fn process(
+        &mut self,
+        event: MessagesResponseEvent,
+        latency: Duration,
+    ) -> Result<Vec<StreamEvent>, ModelProviderError> {
+        let mut events = Vec::new();
+        match event {
+            MessagesResponseEvent::Ping => {}
+            MessagesResponseEvent::MessageStart { message } => {
+                self.role = message.role.into();
+                self.usage = Usage {
+                    input_tokens: message.usage.input_tokens.into(),
+                    output_tokens: message.usage.output_tokens.into(),
+                    cache_read_tokens: message
+                        .usage
+                        .cache_read_input_tokens
+                        .unwrap_or_default()
+                        .into(),
+                    cache_write_tokens: message
+                        .usage
+                        .cache_creation_input_tokens
+                        .unwrap_or_default()
+                        .into(),
+                };
+                events.push(StreamEvent::MessageStart {
+                    role: self.role.clone(),
+                });
+            }
+            MessagesResponseEvent::ContentBlockStart {
+                index,
+                content_block,
+            } => {
+                match content_block {
+                    AnthropicContentBlock::Text { .. } => {
+                        self.text.clear();
+                        events.push(StreamEvent::TextStart { index });
+                    }
+                    AnthropicContentBlock::ToolUse { id, name, .. } => {
+                        self.tool_id = id.clone();
+                        self.tool_name = name.clone();
+                        self.tool_input.clear();
+                        events.push(StreamEvent::ToolUseStart { index, id, name });
+                    }
+                    AnthropicContentBlock::Thinking { .. } => {
+                        self.reasoning = Some(ReasoningBlock {
+                            text: String::new(),
+                            signature: String::new(),
+                            redacted: Vec::new(),
+                        });
+                        events.push(StreamEvent::ReasoningStart { index });
+                    }
+                    AnthropicContentBlock::RedactedThinking { data } => {
+                        // Redacted thinking arrives whole, base64 encoded, and is only echoed back.
+                        let redacted = STANDARD.decode(data)?;
+                        self.reasoning = Some(ReasoningBlock {
+                            text: String::new(),
+                            signature: String::new(),
+                            redacted: redacted.clone(),
+                        });
+                        events.push(StreamEvent::ReasoningStart { index });
+                        events.push(StreamEvent::ReasoningDelta {
+                            index,
+                            text: None,
+                            signature: None,
+                            redacted: Some(redacted),
+                        });
+                    }
+                    _ => {}
+                }
+            }
+            MessagesResponseEvent::ContentBlockDelta { index, delta } => match delta {
+                ContentBlockDelta::TextDelta { text } => {
+                    self.text.push_str(&text);
+                    events.push(StreamEvent::TextDelta { index, delta: text });
+                }
+                ContentBlockDelta::InputJsonDelta { partial_json } => {
+                    self.tool_input.push_str(&partial_json);
+                    events.push(StreamEvent::ToolInputDelta {
+                        index,
+                        delta: partial_json,
+                    });
+                }
+                ContentBlockDelta::ThinkingDelta { thinking } => {
+                    if let Some(reasoning) = &mut self.reasoning {
+                        reasoning.text.push_str(&thinking);
+                    }
+                    events.push(StreamEvent::ReasoningDelta {
+                        index,
+                        text: Some(thinking),
+                        signature: None,
+                        redacted: None,
+                    });
+                }
+                ContentBlockDelta::SignatureDelta { signature } => {
+                    if let Some(reasoning) = &mut self.reasoning {
+                        reasoning.signature.push_str(&signature);
+                    }
+                    events.push(StreamEvent::ReasoningDelta {
+                        index,
+                        text: None,
+                        signature: Some(signature),
+                        redacted: None,
+                    });
+                }
+            },
+            MessagesResponseEvent::ContentBlockStop { index } => {
+                let block = if let Some(reasoning) = self.reasoning.take() {
+                    ContentBlock::Reasoning(reasoning)
+                } else if !self.tool_id.is_empty() {
+                    let input: serde_json::Value =
+                        serde_json::from_str(&self.tool_input).unwrap_or(serde_json::Value::Null);
+                    let block = ContentBlock::ToolUse(ToolUseBlock {
+                        id: std::mem::take(&mut self.tool_id),
+                        name: std::mem::take(&mut self.tool_name),
+                        input,
+                    });
+                    self.tool_input.clear();
+                    block
+                } else {
+                    ContentBlock::Text(TextBlock(std::mem::take(&mut self.text)))
+                };
+
+                self.content.push(block.clone());
+
+                events.push(StreamEvent::ContentBlockComplete { index, block });
+            }
+            MessagesResponseEvent::MessageDelta {
+                delta,
+                usage: delta_usage,
+            } => {
+                if let Some(reason) = delta.stop_reason {
+                    self.stop_reason = reason.into();
+                }
+                // The output token count in `message_delta` is cumulative.
+                self.usage.output_tokens = delta_usage.output_tokens.into();
+            }
+            MessagesResponseEvent::MessageStop => {
+                events.push(StreamEvent::Metadata {
+                    usage: self.usage,
+                    latency,
+                });
+
+                let message = Message {
+                    role: self.role.clone(),
+                    content: std::mem::take(&mut self.content),
+                };
+
+                events.push(StreamEvent::MessageComplete {
+                    message,
+                    stop_reason: self.stop_reason.clone(),
+                });
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// Maps an error from the Anthropic client, recognizing prompts that exceed the context window.
+fn api_error<E>(error: E) -> ModelProviderError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    // Anthropic reports the overflow as an `invalid_request_error`, so it is recognized by the
+    // API's message, which the client error carries in its display or debug representation.
+    if is_context_overflow(&format!("{error} {error:?}")) {
+        Error::ContextWindowOverflow.into()
+    } else {
+        error.into()
+    }
+}
+
+/// Returns true if an error message says the prompt does not fit the model's context window.
+fn is_context_overflow(message: &str) -> bool {
+    message.contains("prompt is too long")
 }
 
 impl From<AnthropicRole> for Role {
@@ -188,24 +468,30 @@ impl From<AnthropicStopReason> for StopReason {
     }
 }
 
-impl From<&ToolPolicy> for AnthropicToolChoice {
-    fn from(policy: &ToolPolicy) -> Self {
-        match policy {
-            ToolPolicy::Auto => AnthropicToolChoice::Auto {
-                disable_parallel_tool_use: false,
-            },
-            ToolPolicy::None => AnthropicToolChoice::Auto {
-                disable_parallel_tool_use: false,
-            },
-            ToolPolicy::Required => AnthropicToolChoice::Any {
-                disable_parallel_tool_use: false,
-            },
-            ToolPolicy::Specific { name } => AnthropicToolChoice::Tool {
-                tool_name: name.clone(),
-                disable_parallel_tool_use: false,
-            },
-        }
-    }
+/// Maps the tool policy and parallel tool use setting to an Anthropic tool choice. Disabling
+/// parallel tool use without a policy requires an explicit `auto` choice.
+fn tool_choice(args: &StreamArgs) -> Option<AnthropicToolChoice> {
+    let disable_parallel_tool_use = args.parallel_tool_use == Some(false);
+
+    let policy = match &args.tool_policy {
+        Some(policy) => policy,
+        None if disable_parallel_tool_use => &ToolPolicy::Auto,
+        None => return None,
+    };
+
+    Some(match policy {
+        ToolPolicy::Auto => AnthropicToolChoice::Auto {
+            disable_parallel_tool_use,
+        },
+        ToolPolicy::None => AnthropicToolChoice::None,
+        ToolPolicy::Required => AnthropicToolChoice::Any {
+            disable_parallel_tool_use,
+        },
+        ToolPolicy::Specific { name } => AnthropicToolChoice::Tool {
+            tool_name: name.clone(),
+            disable_parallel_tool_use,
+        },
+    })
 }
 
 impl From<&ToolSpec> for AnthropicTool<'static> {
@@ -229,9 +515,11 @@ impl From<&ToolSpec> for AnthropicTool<'static> {
     }
 }
 
-impl From<&ContentBlock> for Option<AnthropicContentBlock> {
-    fn from(block: &ContentBlock) -> Self {
-        match block {
+impl TryFrom<&ContentBlock> for Option<AnthropicContentBlock> {
+    type Error = AnthropicError;
+
+    fn try_from(block: &ContentBlock) -> Result<Self, Self::Error> {
+        Ok(match block {
             ContentBlock::Text(TextBlock(text)) => Some(AnthropicContentBlock::Text {
                 text: text.clone(),
                 cache_control: None,
@@ -246,8 +534,7 @@ impl From<&ContentBlock> for Option<AnthropicContentBlock> {
             ContentBlock::ToolResult(result) => {
                 let items = result.content.as_ref().unwrap_or_else(|e| e);
 
-                let anthropic_content: Vec<AnthropicToolResultContentBlock> =
-                    items.iter().map(|item| item.into()).collect();
+                let anthropic_content = items.iter().map(|item| item.into()).collect();
 
                 Some(AnthropicContentBlock::ToolResult {
                     tool_use_id: result.id.clone(),
@@ -256,34 +543,248 @@ impl From<&ContentBlock> for Option<AnthropicContentBlock> {
                     cache_control: None,
                 })
             }
-            _ => None,
+            // Thinking blocks must be passed back unchanged, with their signature, for the model
+            // to continue reasoning across tool calls. Reasoning without a signature, such as that
+            // of other providers, would be rejected and is left out.
+            ContentBlock::Reasoning(reasoning) if !reasoning.redacted.is_empty() => {
+                Some(AnthropicContentBlock::RedactedThinking {
+                    data: STANDARD.encode(&reasoning.redacted),
+                })
+            }
+            ContentBlock::Reasoning(reasoning) if reasoning.signature.is_empty() => None,
+            ContentBlock::Reasoning(reasoning) => Some(AnthropicContentBlock::Thinking {
+                thinking: reasoning.text.clone(),
+                signature: reasoning.signature.clone(),
+            }),
+            ContentBlock::Image(image) => Some(image.into()),
+            ContentBlock::Document(document) => Some(document.try_into()?),
+            ContentBlock::Video(_) => {
+                return Err(AnthropicError::UnsupportedContent(
+                    "video content blocks".to_string(),
+                ));
+            }
+            ContentBlock::Guard(_) => {
+                return Err(AnthropicError::UnsupportedContent(
+                    "guard content blocks".to_string(),
+                ));
+            }
+            // Cache points mark the previous block and are applied by the message conversion.
+            ContentBlock::CachePoint(_) => None,
+        })
+    }
+}
+
+impl From<&ImageBlock> for AnthropicContentBlock {
+    fn from(image: &ImageBlock) -> Self {
+        let source = match &image.source {
+            ImageSource::Bytes(bytes) => AnthropicImageSource::Base64 {
+                media_type: image.format.mime_type().to_string(),
+                data: STANDARD.encode(bytes),
+            },
+            ImageSource::Url(url) => AnthropicImageSource::Url { url: url.clone() },
+        };
+
+        AnthropicContentBlock::Image {
+            source,
+            cache_control: None,
         }
     }
 }
 
+impl TryFrom<&DocumentBlock> for AnthropicContentBlock {
+    type Error = AnthropicError;
+
+    /// Anthropic reads PDFs and plain text, so text-based formats given as bytes are sent as
+    /// plain text and other binary formats are rejected.
+    fn try_from(document: &DocumentBlock) -> Result<Self, Self::Error> {
+        let unsupported = || {
+            AnthropicError::UnsupportedContent(format!(
+                "document {} in {} format",
+                document.name,
+                document.format.mime_type()
+            ))
+        };
+
+        let source = match (&document.source, &document.format) {
+            (DocumentSource::Bytes(bytes), DocumentFormat::Pdf) => {
+                AnthropicDocumentSource::Base64 {
+                    media_type: DocumentFormat::Pdf.mime_type().to_string(),
+                    data: STANDARD.encode(bytes),
+                }
+            }
+            (DocumentSource::Url(url), DocumentFormat::Pdf) => {
+                AnthropicDocumentSource::Url { url: url.clone() }
+            }
+            (DocumentSource::Bytes(bytes), format) if is_text_format(format) => {
+                AnthropicDocumentSource::Text {
+                    media_type: "text/plain".to_string(),
+                    data: String::from_utf8(bytes.clone()).map_err(|_| unsupported())?,
+                }
+            }
+            // Only PDFs can be fetched from a URL.
+            (DocumentSource::Bytes(_) | DocumentSource::Url(_), _) => return Err(unsupported()),
+            (DocumentSource::Text(text), _) => AnthropicDocumentSource::Text {
+                media_type: "text/plain".to_string(),
+                data: text.clone(),
+            },
+            (DocumentSource::Structured(blocks), _) => AnthropicDocumentSource::Text {
+                media_type: "text/plain".to_string(),
+                data: blocks
+                    .iter()
+                    .map(|b| b.0.as_str())
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            },
+        };
+
+        Ok(AnthropicContentBlock::Document {
+            source,
+            title: Some(document.name.clone()),
+            context: document.context.clone(),
+            citations: document
+                .citations
+                .then_some(AnthropicCitations { enabled: true }),
+            cache_control: None,
+        })
+    }
+}
+
+fn is_text_format(format: &DocumentFormat) -> bool {
+    matches!(
+        format,
+        DocumentFormat::Txt
+            | DocumentFormat::Md
+            | DocumentFormat::Csv
+            | DocumentFormat::Html
+            | DocumentFormat::Json
+            | DocumentFormat::Xml
+    )
+}
+
 impl From<&ToolResultContent> for AnthropicToolResultContentBlock {
+    /// Tool results are sent as text, so images and documents in them are replaced with a note
+    /// rather than failing every later request that includes them.
     fn from(item: &ToolResultContent) -> Self {
-        match item {
-            ToolResultContent::Text(TextBlock(text)) => {
-                AnthropicToolResultContentBlock::Text { text: text.clone() }
+        let text = match item {
+            ToolResultContent::Text(TextBlock(text)) => text.clone(),
+            ToolResultContent::Json(json) => json.0.to_string(),
+            ToolResultContent::Image(_) => {
+                "[image omitted: not supported by this provider]".to_string()
             }
-            ToolResultContent::Json(json) => AnthropicToolResultContentBlock::Text {
-                text: json.0.to_string(),
-            },
-        }
+            ToolResultContent::Document(document) => format!(
+                "[document {} omitted: not supported by this provider]",
+                document.name
+            ),
+        };
+
+        AnthropicToolResultContentBlock::Text { text }
     }
 }
 
-impl From<&Message> for AnthropicInputMessage {
-    fn from(message: &Message) -> Self {
-        AnthropicInputMessage {
+impl TryFrom<&Message> for AnthropicInputMessage {
+    type Error = AnthropicError;
+
+    fn try_from(message: &Message) -> Result<Self, Self::Error> {
+        let mut blocks = Vec::new();
+        for block in &message.content {
+            if let ContentBlock::CachePoint(_) = block {
+                if let Some(previous) = blocks.last_mut() {
+                    set_cache_control(previous);
+                }
+                continue;
+            }
+
+            blocks.extend(Option::<AnthropicContentBlock>::try_from(block)?);
+        }
+
+        Ok(AnthropicInputMessage {
             role: (&message.role).into(),
-            content: AnthropicContent::Blocks(
-                message.content.iter().filter_map(|b| b.into()).collect(),
-            ),
+            content: AnthropicContent::Blocks(blocks),
             ..Default::default()
+        })
+    }
+}
+
+/// Converts structured system prompt blocks into text blocks, attaching each cache point to the
+/// block before it. Anthropic has no guard content, so guard blocks are rejected.
+fn system_blocks(
+    blocks: &[SystemPromptBlock],
+) -> Result<Vec<AnthropicContentBlock>, AnthropicError> {
+    let mut system = Vec::new();
+    for block in blocks {
+        match block {
+            SystemPromptBlock::Text(TextBlock(text)) => system.push(AnthropicContentBlock::Text {
+                text: text.clone(),
+                cache_control: None,
+                citations: None,
+            }),
+            SystemPromptBlock::CachePoint(_) => {
+                if let Some(previous) = system.last_mut() {
+                    set_cache_control(previous);
+                }
+            }
+            SystemPromptBlock::Guard(_) => {
+                return Err(AnthropicError::UnsupportedContent(
+                    "guard content in the system prompt".to_string(),
+                ));
+            }
         }
     }
+
+    Ok(system)
+}
+
+/// The most cache breakpoints Anthropic accepts in one request.
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+/// Makes `block` a cache breakpoint, so Anthropic caches the prompt up to and including it.
+/// Thinking blocks cannot be breakpoints and are left unchanged.
+fn set_cache_control(block: &mut AnthropicContentBlock) {
+    if let Some(cache_control) = cache_control(block) {
+        *cache_control = Some(AnthropicCacheControl::Ephemeral);
+    }
+}
+
+fn cache_control(block: &mut AnthropicContentBlock) -> Option<&mut Option<AnthropicCacheControl>> {
+    match block {
+        AnthropicContentBlock::Text { cache_control, .. }
+        | AnthropicContentBlock::ToolUse { cache_control, .. }
+        | AnthropicContentBlock::ToolResult { cache_control, .. }
+        | AnthropicContentBlock::Image { cache_control, .. }
+        | AnthropicContentBlock::Document { cache_control, .. } => Some(cache_control),
+        _ => None,
+    }
+}
+
+/// Drops the earliest cache breakpoints beyond the number Anthropic accepts. The prompt is read
+/// as tools, then system prompt, then messages, so the latest breakpoints cache the most of it.
+fn limit_cache_breakpoints(
+    tools: Option<&mut Vec<AnthropicTool<'_>>>,
+    system: Option<&mut AnthropicContent>,
+    messages: &mut [AnthropicInputMessage],
+) {
+    let tool_breakpoints = tools
+        .into_iter()
+        .flatten()
+        .map(|tool| &mut tool.cache_control);
+    let block_breakpoints = system
+        .into_iter()
+        .chain(messages.iter_mut().map(|message| &mut message.content))
+        .flat_map(|content| match content {
+            AnthropicContent::Blocks(blocks) => {
+                blocks.iter_mut().filter_map(cache_control).collect()
+            }
+            _ => Vec::new(),
+        });
+
+    let mut breakpoints: Vec<_> = tool_breakpoints
+        .chain(block_breakpoints)
+        .filter(|cache_control| cache_control.is_some())
+        .collect();
+    let excess = breakpoints.len().saturating_sub(MAX_CACHE_BREAKPOINTS);
+    for cache_control in &mut breakpoints[..excess] {
+        **cache_control = None;
+    }
 }
 
 impl From<&SystemPrompt> for String {
@@ -301,3 +802,366 @@ impl From<&SystemPrompt> for String {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::message::{
+        CachePointBlock, GuardBlock, GuardText, ImageFormat, JsonBlock, ToolResultBlock,
+    };
+
+    /// Parses events as the Messages API streams them.
+    fn process(events: Vec<serde_json::Value>) -> Vec<StreamEvent> {
+        let mut state = MessageStreamState::default();
+        events
+            .into_iter()
+            .flat_map(|event| {
+                let event = serde_json::from_value(event).unwrap();
+                state.process(event, Duration::ZERO).unwrap()
+            })
+            .collect()
+    }
+
+    fn thinking_response() -> Vec<StreamEvent> {
+        process(vec![
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "thinking", "thinking": "", "signature": "" },
+            }),
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "thinking_delta", "thinking": "Oslo is " },
+            }),
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "thinking_delta", "thinking": "in Norway." },
+            }),
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": { "type": "signature_delta", "signature": "c2ln" },
+            }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({
+                "type": "content_block_start",
+                "index": 1,
+                "content_block": { "type": "redacted_thinking", "data": "ZW5jcnlwdGVk" },
+            }),
+            json!({ "type": "content_block_stop", "index": 1 }),
+            json!({
+                "type": "content_block_start",
+                "index": 2,
+                "content_block": { "type": "text", "text": "" },
+            }),
+            json!({
+                "type": "content_block_delta",
+                "index": 2,
+                "delta": { "type": "text_delta", "text": "Sunny." },
+            }),
+            json!({ "type": "content_block_stop", "index": 2 }),
+            json!({
+                "type": "message_delta",
+                "delta": { "stop_reason": "end_turn", "stop_sequence": null },
+                "usage": { "output_tokens": 20 },
+            }),
+            json!({ "type": "message_stop" }),
+        ])
+    }
+
+    #[test]
+    fn streams_thinking_signature_and_redacted_blocks() {
+        let events = thinking_response();
+
+        assert!(matches!(
+            events[0],
+            StreamEvent::ReasoningStart { index: 0 }
+        ));
+        let reasoning_deltas: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ReasoningDelta {
+                    index,
+                    text,
+                    signature,
+                    redacted,
+                } => Some((*index, text.clone(), signature.clone(), redacted.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            reasoning_deltas,
+            [
+                (0, Some("Oslo is ".to_string()), None, None),
+                (0, Some("in Norway.".to_string()), None, None),
+                (0, None, Some("c2ln".to_string()), None),
+                (1, None, None, Some(b"encrypted".to_vec())),
+            ]
+        );
+
+        let Some(StreamEvent::MessageComplete {
+            message,
+            stop_reason,
+        }) = events.last()
+        else {
+            panic!("stream did not complete the message");
+        };
+        assert!(matches!(stop_reason, StopReason::EndTurn));
+        assert!(matches!(
+            &message.content[0],
+            ContentBlock::Reasoning(reasoning)
+                if reasoning.text == "Oslo is in Norway."
+                    && reasoning.signature == "c2ln"
+                    && reasoning.redacted.is_empty()
+        ));
+        assert!(matches!(
+            &message.content[1],
+            ContentBlock::Reasoning(reasoning)
+                if reasoning.text.is_empty() && reasoning.redacted == b"encrypted"
+        ));
+        assert!(
+            matches!(&message.content[2], ContentBlock::Text(TextBlock(text)) if text == "Sunny.")
+        );
+    }
+
+    #[test]
+    fn round_trips_reasoning_blocks() {
+        let Some(StreamEvent::MessageComplete { message, .. }) = thinking_response().pop() else {
+            panic!("stream did not complete the message");
+        };
+
+        let input =
+            serde_json::to_value(AnthropicInputMessage::try_from(&message).unwrap()).unwrap();
+        let content = input["content"].as_array().unwrap();
+
+        assert_eq!(content.len(), 3);
+        assert_eq!(content[0]["type"], "thinking");
+        assert_eq!(content[0]["thinking"], "Oslo is in Norway.");
+        assert_eq!(content[0]["signature"], "c2ln");
+        assert_eq!(content[1]["type"], "redacted_thinking");
+        assert_eq!(content[1]["data"], "ZW5jcnlwdGVk");
+        assert_eq!(content[2]["type"], "text");
+    }
+
+    #[test]
+    fn round_trips_redacted_thinking_as_raw_bytes() {
+        let redacted = vec![0xff, 0x00, 0x80, b'x'];
+        let message = Message {
+            role: Role::Assistant,
+            content: vec![ContentBlock::Reasoning(ReasoningBlock {
+                text: String::new(),
+                signature: String::new(),
+                redacted: redacted.clone(),
+            })],
+        };
+
+        let input =
+            serde_json::to_value(AnthropicInputMessage::try_from(&message).unwrap()).unwrap();
+        let data = input["content"][0]["data"].clone();
+        assert_eq!(data, STANDARD.encode(&redacted));
+
+        let events = process(vec![
+            json!({
+                "type": "content_block_start",
+                "index": 0,
+                "content_block": { "type": "redacted_thinking", "data": data },
+            }),
+            json!({ "type": "content_block_stop", "index": 0 }),
+            json!({ "type": "message_stop" }),
+        ]);
+        let Some(StreamEvent::MessageComplete { message, .. }) = events.last() else {
+            panic!("stream did not complete the message");
+        };
+        assert!(matches!(
+            &message.content[0],
+            ContentBlock::Reasoning(reasoning) if reasoning.redacted == redacted
+        ));
+    }
+
+    #[test]
+    fn leaves_out_reasoning_without_a_signature() {
+        let message = Message {
+            role: Role::Assistant,
+            content: vec![
+                ContentBlock::Reasoning(ReasoningBlock {
+                    text: "Thoughts of another model.".into(),
+                    signature: String::new(),
+                    redacted: Vec::new(),
+                }),
+                ContentBlock::Text(TextBlock("Sunny.".into())),
+            ],
+        };
+
+        let input =
+            serde_json::to_value(AnthropicInputMessage::try_from(&message).unwrap()).unwrap();
+        let content = input["content"].as_array().unwrap();
+
+        assert_eq!(content.len(), 1);
+        assert_eq!(content[0]["type"], "text");
+    }
+
+    #[test]
+    fn replaces_images_and_documents_in_tool_results() {
+        let message = Message {
+            role: Role::User,
+            content: vec![ContentBlock::ToolResult(ToolResultBlock {
+                id: "call_1".into(),
+                content: Ok(vec![
+                    ToolResultContent::Json(JsonBlock(json!({ "rain": true }))),
+                    ToolResultContent::Image(ImageBlock {
+                        format: ImageFormat::Png,
+                        source: ImageSource::Bytes(b"png".to_vec()),
+                    }),
+                    ToolResultContent::Document(DocumentBlock {
+                        name: "report".into(),
+                        format: DocumentFormat::Pdf,
+                        source: DocumentSource::Bytes(b"pdf".to_vec()),
+                        citations: false,
+                        context: None,
+                    }),
+                ]),
+            })],
+        };
+
+        let input =
+            serde_json::to_value(AnthropicInputMessage::try_from(&message).unwrap()).unwrap();
+        let result = &input["content"][0];
+        assert_eq!(result["type"], "tool_result");
+        assert_eq!(result["content"][0]["text"], r#"{"rain":true}"#);
+        assert_eq!(
+            result["content"][1]["text"],
+            "[image omitted: not supported by this provider]"
+        );
+        assert_eq!(
+            result["content"][2]["text"],
+            "[document report omitted: not supported by this provider]"
+        );
+    }
+
+    #[test]
+    fn rejects_guard_content_in_the_system_prompt() {
+        let provider =
+            AnthropicModelProvider::new("key".into(), ApiVersion::Latest, Model::ClaudeSonnet4_5);
+        let mut args = StreamArgs::default();
+        args.system_prompt = Some(SystemPrompt::Structured(vec![
+            SystemPromptBlock::Text(TextBlock("Be brief.".into())),
+            SystemPromptBlock::Guard(GuardBlock::Text(GuardText {
+                qualifiers: Vec::new(),
+                text: "Be kind.".into(),
+            })),
+        ]));
+
+        assert!(matches!(
+            provider.build_request(&[Message::new_user("Hi")], &args),
+            Err(AnthropicError::UnsupportedContent(_))
+        ));
+    }
+
+    #[test]
+    fn keeps_the_latest_four_cache_breakpoints() {
+        let provider =
+            AnthropicModelProvider::new("key".into(), ApiVersion::Latest, Model::ClaudeSonnet4_5);
+        let mut args = StreamArgs::default();
+        args.tool_specs = Some(vec![ToolSpec {
+            name: "weather".into(),
+            ..Default::default()
+        }]);
+        args.tool_cache_point = Some(CachePointBlock::Default);
+        args.system_prompt = Some(SystemPrompt::Structured(vec![
+            SystemPromptBlock::Text(TextBlock("Be brief.".into())),
+            SystemPromptBlock::CachePoint(CachePointBlock::Default),
+        ]));
+        let messages: Vec<_> = ["Hi", "Hello.", "Weather?", "Sunny.", "Thanks"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| Message {
+                role: if i % 2 == 0 {
+                    Role::User
+                } else {
+                    Role::Assistant
+                },
+                content: vec![
+                    ContentBlock::Text(TextBlock(text.into())),
+                    ContentBlock::CachePoint(CachePointBlock::Default),
+                ],
+            })
+            .collect();
+
+        let body =
+            serde_json::to_value(provider.build_request(&messages, &args).unwrap().body).unwrap();
+
+        assert!(body["tools"][0].get("cache_control").is_none());
+        assert!(body["system"][0].get("cache_control").is_none());
+        let cached: Vec<_> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["content"][0].get("cache_control").is_some())
+            .collect();
+        assert_eq!(cached, [false, true, true, true, true]);
+    }
+
+    /// Serializes the body of the request built for `args`.
+    fn request_body(args: &StreamArgs) -> serde_json::Value {
+        let provider =
+            AnthropicModelProvider::new("key".into(), ApiVersion::Latest, Model::ClaudeSonnet4_5);
+        let request = provider
+            .build_request(&[Message::new_user("Hi")], args)
+            .unwrap();
+
+        serde_json::to_value(request.body).unwrap()
+    }
+
+    fn weather_tool() -> Option<Vec<ToolSpec>> {
+        Some(vec![ToolSpec {
+            name: "weather".into(),
+            ..Default::default()
+        }])
+    }
+
+    #[test]
+    fn maps_tool_policies_to_tool_choices() {
+        let choice = |tool_policy, parallel_tool_use| {
+            let mut args = StreamArgs::default();
+            args.tool_specs = weather_tool();
+            args.tool_policy = tool_policy;
+            args.parallel_tool_use = parallel_tool_use;
+            request_body(&args)["tool_choice"].clone()
+        };
+
+        assert_eq!(choice(None, None), serde_json::Value::Null);
+        assert_eq!(
+            choice(None, Some(false)),
+            json!({ "type": "auto", "disable_parallel_tool_use": true })
+        );
+        assert_eq!(choice(Some(ToolPolicy::None), None)["type"], "none");
+        assert_eq!(
+            choice(Some(ToolPolicy::Required), Some(false)),
+            json!({ "type": "any", "disable_parallel_tool_use": true })
+        );
+        assert_eq!(
+            choice(
+                Some(ToolPolicy::Specific {
+                    name: "weather".into()
+                }),
+                None
+            )["name"],
+            "weather"
+        );
+    }
+
+    #[test]
+    fn leaves_out_the_tool_choice_without_tools() {
+        let mut args = StreamArgs::default();
+        args.tool_policy = Some(ToolPolicy::Required);
+        args.parallel_tool_use = Some(false);
+
+        let body = request_body(&args);
+        assert!(body["tool_choice"].is_null());
+        assert!(body["tools"].is_null());
+    }
+}
@@ -0,0 +1,131 @@
+//! Decoding of the `application/vnd.amazon.eventstream` framing used by streaming AWS APIs.
+
+use std::collections::HashMap;
+
+use super::BedrockError;
+
+/// Length of the prelude: total length, headers length and prelude checksum.
+const PRELUDE_LEN: usize = 12;
+/// Length of the checksum that ends every message.
+const CHECKSUM_LEN: usize = 4;
+
+/// A decoded event stream message.
+#[derive(Debug)]
+pub(super) struct Frame {
+    /// The message's string headers, such as `:event-type`. Headers of other types are skipped.
+    pub(super) headers: HashMap<String, String>,
+    pub(super) payload: Vec<u8>,
+}
+
+impl Frame {
+    pub(super) fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+}
+
+/// Incrementally splits a response body into event stream messages.
+#[derive(Debug, Default)]
+pub(super) struct EventStreamDecoder {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamDecoder {
+    /// Consumes a chunk of the response body and returns every message it completes.
+    pub(super) fn push(&mut self, chunk: &[u8]) -> Result<Vec<Frame>, BedrockError> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut frames = Vec::new();
+        while self.buffer.len() >= PRELUDE_LEN {
+            let total_len = read_u32(&self.buffer[0..4]) as usize;
+            if total_len < PRELUDE_LEN + CHECKSUM_LEN {
+                return Err(malformed("message length is too small"));
+            }
+
+            if self.buffer.len() < total_len {
+                break;
+            }
+
+            let message: Vec<u8> = self.buffer.drain(..total_len).collect();
+            frames.push(decode(&message)?);
+        }
+
+        Ok(frames)
+    }
+
+    /// Returns true if part of a message is still waiting for the rest of its bytes.
+    pub(super) fn has_partial_frame(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+}
+
+fn decode(message: &[u8]) -> Result<Frame, BedrockError> {
+    let headers_len = read_u32(&message[4..8]) as usize;
+    if read_u32(&message[8..12]) != crc32fast::hash(&message[..8]) {
+        return Err(malformed("prelude checksum mismatch"));
+    }
+
+    let checksum_start = message.len() - CHECKSUM_LEN;
+    if read_u32(&message[checksum_start..]) != crc32fast::hash(&message[..checksum_start]) {
+        return Err(malformed("message checksum mismatch"));
+    }
+
+    let headers_end = PRELUDE_LEN + headers_len;
+    if headers_end > checksum_start {
+        return Err(malformed("headers exceed the message length"));
+    }
+
+    Ok(Frame {
+        headers: decode_headers(&message[PRELUDE_LEN..headers_end])?,
+        payload: message[headers_end..checksum_start].to_vec(),
+    })
+}
+
+fn decode_headers(mut bytes: &[u8]) -> Result<HashMap<String, String>, BedrockError> {
+    let mut headers = HashMap::new();
+    while !bytes.is_empty() {
+        let name_len = usize::from(bytes[0]);
+        let name = take(&mut bytes, 1 + name_len)?[1..].to_vec();
+        let value_type = take(&mut bytes, 1)?[0];
+
+        let value_len = match value_type {
+            // Boolean true and false carry no value.
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            // Byte arrays and strings are prefixed with their length.
+            6 | 7 => usize::from(u16::from_be_bytes(take(&mut bytes, 2)?.try_into().unwrap())),
+            _ => return Err(malformed("unknown header value type")),
+        };
+
+        let value = take(&mut bytes, value_len)?;
+        if value_type == 7 {
+            headers.insert(
+                String::from_utf8_lossy(&name).into_owned(),
+                String::from_utf8_lossy(value).into_owned(),
+            );
+        }
+    }
+
+    Ok(headers)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], BedrockError> {
+    if bytes.len() < len {
+        return Err(malformed("header is truncated"));
+    }
+
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn malformed(reason: &'static str) -> BedrockError {
+    BedrockError::MalformedEventStream(reason)
+}
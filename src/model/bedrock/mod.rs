@@ -0,0 +1,1030 @@
+mod event_stream;
+mod sigv4;
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant, SystemTime},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    message::{
+        CachePointBlock, ContentBlock, DocumentBlock, DocumentFormat, DocumentSource, GuardBlock,
+        GuardImageFormat, GuardQualifier, ImageBlock, ImageFormat, ImageSource, Message,
+        ReasoningBlock, Role, StopReason, SystemPrompt, SystemPromptBlock, TextBlock,
+        ToolResultContent, ToolUseBlock, VideoFormat, VideoSource,
+    },
+    model::model_provider::{
+        ModelProvider, ModelProviderError, ModelProviderStream, StreamArgs, StreamEvent,
+        ToolPolicy, Usage,
+    },
+    tool::ToolSpec,
+};
+
+use event_stream::{EventStreamDecoder, Frame};
+
+/// Errors reported by Amazon Bedrock.
+#[derive(thiserror::Error, Debug)]
+pub enum BedrockError {
+    #[error("Bedrock responded with {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("Bedrock reported {kind}: {message}")]
+    Exception { kind: String, message: String },
+    #[error("Malformed event stream: {0}")]
+    MalformedEventStream(&'static str),
+}
+
+/// AWS credentials used to sign Bedrock requests.
+#[derive(Clone)]
+pub struct BedrockCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// Session token for temporary credentials.
+    pub session_token: Option<String>,
+}
+
+impl BedrockCredentials {
+    pub fn new(access_key_id: impl Into<String>, secret_access_key: impl Into<String>) -> Self {
+        Self {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    pub fn with_session_token(mut self, session_token: impl Into<String>) -> Self {
+        self.session_token = Some(session_token.into());
+        self
+    }
+
+    /// Reads `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+impl std::fmt::Debug for BedrockCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BedrockCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// A model provider for Amazon Bedrock's ConverseStream API.
+///
+/// Images, documents and videos given as `s3://` URLs are passed to Bedrock as S3 locations.
+/// Bedrock cannot prevent tool use, so [`ToolPolicy::None`] withholds the tools. As Bedrock
+/// rejects tool uses and results without a tool configuration, the conversation's tool history is
+/// then sent as text.
+#[derive(Clone, Debug)]
+pub struct BedrockModelProvider {
+    region: String,
+    model_id: String,
+    credentials: BedrockCredentials,
+    endpoint: String,
+    client: reqwest::Client,
+}
+
+impl BedrockModelProvider {
+    /// Creates a provider for `model_id`, which may also be an inference profile or ARN.
+    pub fn new(
+        region: impl Into<String>,
+        model_id: impl Into<String>,
+        credentials: BedrockCredentials,
+    ) -> Self {
+        let region = region.into();
+
+        Self {
+            endpoint: format!("https://bedrock-runtime.{region}.amazonaws.com"),
+            region,
+            model_id: model_id.into(),
+            credentials,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends requests to `endpoint` instead of the regional Bedrock runtime endpoint.
+    pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoint = endpoint.into();
+        self
+    }
+
+    fn build_request(&self, messages: &[Message], args: &StreamArgs) -> ConverseRequest {
+        let system = match &args.system_prompt {
+            Some(SystemPrompt::Text(text)) => vec![SystemContent::Text(text.clone())],
+            Some(SystemPrompt::Structured(blocks)) => {
+                blocks.iter().map(SystemContent::from).collect()
+            }
+            _ => Vec::new(),
+        };
+
+        let tools_disabled = matches!(args.tool_policy, Some(ToolPolicy::None));
+        let tool_config = match &args.tool_specs {
+            Some(specs) if !specs.is_empty() && !tools_disabled => Some(ToolConfig {
+                tools: specs
+                    .iter()
+                    .map(BedrockTool::from)
+                    .chain(
+                        args.tool_cache_point
+                            .as_ref()
+                            .map(|cache_point| BedrockTool::CachePoint(cache_point.into())),
+                    )
+                    .collect(),
+                tool_choice: args.tool_policy.as_ref().and_then(tool_choice),
+            }),
+            _ => None,
+        };
+
+        ConverseRequest {
+            messages: messages
+                .iter()
+                .map(|message| BedrockMessage::new(message, tools_disabled))
+                .collect(),
+            system,
+            inference_config: InferenceConfig {
+                max_tokens: args.max_tokens,
+                temperature: args.temperature,
+                top_p: args.top_p,
+                stop_sequences: args.stop_sequences.clone(),
+            },
+            tool_config,
+        }
+    }
+}
+
+impl ModelProvider for BedrockModelProvider {
+    fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream {
+        let request = self.build_request(messages, args);
+        let url = format!(
+            "{}/model/{}/converse-stream",
+            self.endpoint.trim_end_matches('/'),
+            sigv4::uri_encode(&self.model_id, true)
+        );
+        let credentials = self.credentials.clone();
+        let region = self.region.clone();
+        let client = self.client.clone();
+
+        Box::pin(async_stream::try_stream! {
+            let started = Instant::now();
+            let url = reqwest::Url::parse(&url)?;
+            let body = serde_json::to_vec(&request)?;
+            let signature = sigv4::sign(
+                &credentials,
+                &region,
+                "bedrock",
+                "POST",
+                &url,
+                &body,
+                SystemTime::now(),
+            );
+
+            let mut builder = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(reqwest::header::ACCEPT, "application/vnd.amazon.eventstream");
+            for (name, value) in signature {
+                builder = builder.header(name, value);
+            }
+
+            let mut response = builder.body(body).send().await?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await?;
+                let error: ModelProviderError = if is_context_overflow(&body) {
+                    Error::ContextWindowOverflow.into()
+                } else {
+                    BedrockError::Status { status, body }.into()
+                };
+
+                Err(error)?;
+                return;
+            }
+
+            let mut decoder = EventStreamDecoder::default();
+            let mut state = ConverseStreamState::default();
+
+            while let Some(chunk) = response.chunk().await? {
+                for frame in decoder.push(&chunk)? {
+                    for event in state.process(frame, started.elapsed())? {
+                        yield event;
+                    }
+                }
+            }
+
+            if decoder.has_partial_frame() {
+                Err(BedrockError::MalformedEventStream("stream ended inside a message"))?;
+                return;
+            }
+
+            if let Some(event) = state.finish() {
+                yield event;
+            }
+        })
+    }
+}
+
+/// Returns true if an error message says the prompt does not fit the model's context window.
+fn is_context_overflow(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "input is too long",
+        "prompt is too long",
+        "too many input tokens",
+        "context window",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConverseRequest {
+    messages: Vec<BedrockMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    system: Vec<SystemContent>,
+    inference_config: InferenceConfig,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<ToolConfig>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InferenceConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolConfig {
+    tools: Vec<BedrockTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockTool {
+    ToolSpec {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(rename = "inputSchema")]
+        input_schema: InputSchema,
+    },
+    CachePoint(BedrockCachePoint),
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum InputSchema {
+    Json(serde_json::Map<String, serde_json::Value>),
+}
+
+impl From<&ToolSpec> for BedrockTool {
+    fn from(spec: &ToolSpec) -> Self {
+        BedrockTool::ToolSpec {
+            name: spec.name.clone(),
+            description: spec.description.clone(),
+            input_schema: InputSchema::Json(spec.input_schema.clone()),
+        }
+    }
+}
+
+fn tool_choice(policy: &ToolPolicy) -> Option<serde_json::Value> {
+    match policy {
+        ToolPolicy::Auto => Some(serde_json::json!({ "auto": {} })),
+        ToolPolicy::Required => Some(serde_json::json!({ "any": {} })),
+        ToolPolicy::Specific { name } => Some(serde_json::json!({ "tool": { "name": name } })),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum SystemContent {
+    Text(String),
+    GuardContent(BedrockGuard),
+    CachePoint(BedrockCachePoint),
+}
+
+impl From<&SystemPromptBlock> for SystemContent {
+    fn from(block: &SystemPromptBlock) -> Self {
+        match block {
+            SystemPromptBlock::Text(TextBlock(text)) => SystemContent::Text(text.clone()),
+            SystemPromptBlock::Guard(guard) => SystemContent::GuardContent(guard.into()),
+            SystemPromptBlock::CachePoint(cache_point) => {
+                SystemContent::CachePoint(cache_point.into())
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BedrockMessage {
+    role: &'static str,
+    content: Vec<BedrockContentBlock>,
+}
+
+impl BedrockMessage {
+    /// Converts `message`, describing its tool uses and results as text if `tools_as_text` is set.
+    fn new(message: &Message, tools_as_text: bool) -> Self {
+        BedrockMessage {
+            role: match message.role {
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            },
+            content: message
+                .content
+                .iter()
+                .map(|block| match tool_block_text(block) {
+                    Some(text) if tools_as_text => BedrockContentBlock::Text(text),
+                    _ => BedrockContentBlock::from(block),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Describes a tool use or tool result as text.
+fn tool_block_text(block: &ContentBlock) -> Option<String> {
+    match block {
+        ContentBlock::ToolUse(tool_use) => Some(format!(
+            "[tool call {}: {} with input {}]",
+            tool_use.id, tool_use.name, tool_use.input
+        )),
+        ContentBlock::ToolResult(result) => {
+            let (outcome, items) = match &result.content {
+                Ok(items) => ("result", items),
+                Err(items) => ("error", items),
+            };
+            let content = items
+                .iter()
+                .map(|item| match item {
+                    ToolResultContent::Text(TextBlock(text)) => text.clone(),
+                    ToolResultContent::Json(json) => json.0.to_string(),
+                    ToolResultContent::Image(_) => "[image]".to_string(),
+                    ToolResultContent::Document(document) => {
+                        format!("[document {}]", document.name)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            Some(format!("[tool {outcome} for {}: {content}]", result.id))
+        }
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockContentBlock {
+    Text(String),
+    Image(BedrockImage),
+    Document(BedrockDocument),
+    Video(BedrockVideo),
+    ToolUse(BedrockToolUse),
+    ToolResult(BedrockToolResult),
+    GuardContent(BedrockGuard),
+    CachePoint(BedrockCachePoint),
+    ReasoningContent(BedrockReasoning),
+}
+
+impl From<&ContentBlock> for BedrockContentBlock {
+    fn from(block: &ContentBlock) -> Self {
+        match block {
+            ContentBlock::Text(TextBlock(text)) => BedrockContentBlock::Text(text.clone()),
+            ContentBlock::Image(image) => match BedrockImage::new(image) {
+                Some(image) => BedrockContentBlock::Image(image),
+                None => BedrockContentBlock::Text(
+                    "[image omitted: only bytes and s3:// URLs are supported]".to_string(),
+                ),
+            },
+            ContentBlock::Document(document) => match BedrockDocument::new(document) {
+                Some(document) => BedrockContentBlock::Document(document),
+                None => BedrockContentBlock::Text(format!(
+                    "[document {} omitted: only bytes, text and s3:// URLs are supported]",
+                    document.name
+                )),
+            },
+            ContentBlock::Video(video) => BedrockContentBlock::Video(BedrockVideo {
+                format: video_format(&video.format),
+                source: match &video.source {
+                    VideoSource::Bytes(bytes) => BedrockSource::Bytes(STANDARD.encode(bytes)),
+                },
+            }),
+            ContentBlock::ToolUse(tool_use) => BedrockContentBlock::ToolUse(BedrockToolUse {
+                tool_use_id: tool_use.id.clone(),
+                name: tool_use.name.clone(),
+                input: tool_use.input.clone(),
+            }),
+            ContentBlock::ToolResult(result) => {
+                let items = result.content.as_ref().unwrap_or_else(|e| e);
+                BedrockContentBlock::ToolResult(BedrockToolResult {
+                    tool_use_id: result.id.clone(),
+                    content: items.iter().map(BedrockToolResultContent::from).collect(),
+                    status: if result.content.is_ok() {
+                        "success"
+                    } else {
+                        "error"
+                    },
+                })
+            }
+            ContentBlock::Guard(guard) => BedrockContentBlock::GuardContent(guard.into()),
+            ContentBlock::CachePoint(cache_point) => {
+                BedrockContentBlock::CachePoint(cache_point.into())
+            }
+            ContentBlock::Reasoning(reasoning) => {
+                BedrockContentBlock::ReasoningContent(reasoning.into())
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockSource {
+    Bytes(String),
+    S3Location { uri: String },
+}
+
+impl BedrockSource {
+    /// Bedrock reads media from S3 but not from arbitrary URLs.
+    fn from_url(url: &str) -> Option<Self> {
+        url.starts_with("s3://").then(|| BedrockSource::S3Location {
+            uri: url.to_string(),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct BedrockImage {
+    format: &'static str,
+    source: BedrockSource,
+}
+
+impl BedrockImage {
+    fn new(image: &ImageBlock) -> Option<Self> {
+        Some(BedrockImage {
+            format: match image.format {
+                ImageFormat::Png => "png",
+                ImageFormat::Jpeg => "jpeg",
+                ImageFormat::Gif => "gif",
+                ImageFormat::Webp => "webp",
+            },
+            source: match &image.source {
+                ImageSource::Bytes(bytes) => BedrockSource::Bytes(STANDARD.encode(bytes)),
+                ImageSource::Url(url) => BedrockSource::from_url(url)?,
+            },
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct BedrockDocument {
+    format: &'static str,
+    name: String,
+    source: BedrockDocumentSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    context: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    citations: Option<BedrockCitations>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockDocumentSource {
+    Bytes(String),
+    Text(String),
+    Content(Vec<BedrockDocumentContent>),
+    S3Location { uri: String },
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockDocumentContent {
+    Text(String),
+}
+
+#[derive(Serialize)]
+struct BedrockCitations {
+    enabled: bool,
+}
+
+impl BedrockDocument {
+    fn new(document: &DocumentBlock) -> Option<Self> {
+        Some(BedrockDocument {
+            // Bedrock has no JSON or XML format, so those documents are sent as plain text.
+            format: match document.format {
+                DocumentFormat::Pdf => "pdf",
+                DocumentFormat::Csv => "csv",
+                DocumentFormat::Doc => "doc",
+                DocumentFormat::Docx => "docx",
+                DocumentFormat::Xls => "xls",
+                DocumentFormat::Xlsx => "xlsx",
+                DocumentFormat::Html => "html",
+                DocumentFormat::Md => "md",
+                DocumentFormat::Txt | DocumentFormat::Json | DocumentFormat::Xml => "txt",
+            },
+            name: document.name.clone(),
+            source: match &document.source {
+                DocumentSource::Bytes(bytes) => {
+                    BedrockDocumentSource::Bytes(STANDARD.encode(bytes))
+                }
+                DocumentSource::Text(text) => BedrockDocumentSource::Text(text.clone()),
+                DocumentSource::Structured(blocks) => BedrockDocumentSource::Content(
+                    blocks
+                        .iter()
+                        .map(|TextBlock(text)| BedrockDocumentContent::Text(text.clone()))
+                        .collect(),
+                ),
+                DocumentSource::Url(url) => match BedrockSource::from_url(url)? {
+                    BedrockSource::S3Location { uri } => BedrockDocumentSource::S3Location { uri },
+                    BedrockSource::Bytes(_) => return None,
+                },
+            },
+            context: document.context.clone(),
+            citations: document
+                .citations
+                .then_some(BedrockCitations { enabled: true }),
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct BedrockVideo {
+    format: &'static str,
+    source: BedrockSource,
+}
+
+fn video_format(format: &VideoFormat) -> &'static str {
+    match format {
+        VideoFormat::Mkv => "mkv",
+        VideoFormat::Mov => "mov",
+        VideoFormat::Mp4 => "mp4",
+        VideoFormat::Webm => "webm",
+        VideoFormat::Flv => "flv",
+        VideoFormat::Mpeg => "mpeg",
+        VideoFormat::Wmv => "wmv",
+        VideoFormat::Tgp => "three_gp",
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockToolUse {
+    tool_use_id: String,
+    name: String,
+    input: serde_json::Value,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BedrockToolResult {
+    tool_use_id: String,
+    content: Vec<BedrockToolResultContent>,
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockToolResultContent {
+    Text(String),
+    Json(serde_json::Value),
+    Image(BedrockImage),
+    Document(BedrockDocument),
+}
+
+impl From<&ToolResultContent> for BedrockToolResultContent {
+    fn from(item: &ToolResultContent) -> Self {
+        match item {
+            ToolResultContent::Text(TextBlock(text)) => {
+                BedrockToolResultContent::Text(text.clone())
+            }
+            ToolResultContent::Json(json) => BedrockToolResultContent::Json(json.0.clone()),
+            ToolResultContent::Image(image) => match BedrockImage::new(image) {
+                Some(image) => BedrockToolResultContent::Image(image),
+                None => BedrockToolResultContent::Text(
+                    "[image omitted: only bytes and s3:// URLs are supported]".to_string(),
+                ),
+            },
+            ToolResultContent::Document(document) => match BedrockDocument::new(document) {
+                Some(document) => BedrockToolResultContent::Document(document),
+                None => BedrockToolResultContent::Text(format!(
+                    "[document {} omitted: only bytes, text and s3:// URLs are supported]",
+                    document.name
+                )),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockGuard {
+    Text {
+        text: String,
+        qualifiers: Vec<&'static str>,
+    },
+    Image {
+        format: &'static str,
+        source: BedrockSource,
+    },
+}
+
+impl From<&GuardBlock> for BedrockGuard {
+    fn from(guard: &GuardBlock) -> Self {
+        match guard {
+            GuardBlock::Text(text) => BedrockGuard::Text {
+                text: text.text.clone(),
+                qualifiers: text
+                    .qualifiers
+                    .iter()
+                    .map(|qualifier| match qualifier {
+                        GuardQualifier::GroundingSource => "grounding_source",
+                        GuardQualifier::Query => "query",
+                        GuardQualifier::GuardContent => "guard_content",
+                    })
+                    .collect(),
+            },
+            GuardBlock::Image(image) => BedrockGuard::Image {
+                format: match image.format {
+                    GuardImageFormat::Png => "png",
+                    GuardImageFormat::Jpeg => "jpeg",
+                },
+                source: BedrockSource::Bytes(STANDARD.encode(&image.source)),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct BedrockCachePoint {
+    r#type: &'static str,
+}
+
+impl From<&CachePointBlock> for BedrockCachePoint {
+    fn from(cache_point: &CachePointBlock) -> Self {
+        match cache_point {
+            CachePointBlock::Default => BedrockCachePoint { r#type: "default" },
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+enum BedrockReasoning {
+    ReasoningText {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<String>,
+    },
+    RedactedContent(String),
+}
+
+impl From<&ReasoningBlock> for BedrockReasoning {
+    fn from(reasoning: &ReasoningBlock) -> Self {
+        if !reasoning.redacted.is_empty() {
+            return BedrockReasoning::RedactedContent(STANDARD.encode(&reasoning.redacted));
+        }
+
+        BedrockReasoning::ReasoningText {
+            text: reasoning.text.clone(),
+            signature: (!reasoning.signature.is_empty()).then(|| reasoning.signature.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentBlockStartEvent {
+    content_block_index: usize,
+    start: Option<BlockStart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockStart {
+    tool_use: Option<ToolUseStart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolUseStart {
+    tool_use_id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentBlockDeltaEvent {
+    content_block_index: usize,
+    delta: BlockDelta,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlockDelta {
+    text: Option<String>,
+    tool_use: Option<ToolUseDelta>,
+    reasoning_content: Option<ReasoningDelta>,
+}
+
+#[derive(Deserialize)]
+struct ToolUseDelta {
+    input: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ReasoningDelta {
+    text: Option<String>,
+    signature: Option<String>,
+    redacted_content: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentBlockStopEvent {
+    content_block_index: usize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageStopEvent {
+    stop_reason: String,
+}
+
+#[derive(Deserialize)]
+struct MetadataEvent {
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TokenUsage {
+    #[serde(default)]
+    input_tokens: u64,
+    #[serde(default)]
+    output_tokens: u64,
+    #[serde(default)]
+    cache_read_input_tokens: u64,
+    #[serde(default)]
+    cache_write_input_tokens: u64,
+}
+
+impl From<TokenUsage> for Usage {
+    fn from(usage: TokenUsage) -> Self {
+        Usage {
+            input_tokens: usage.input_tokens,
+            output_tokens: usage.output_tokens,
+            cache_read_tokens: usage.cache_read_input_tokens,
+            cache_write_tokens: usage.cache_write_input_tokens,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ExceptionPayload {
+    #[serde(alias = "Message")]
+    message: Option<String>,
+}
+
+/// A content block that is still receiving deltas.
+enum PendingBlock {
+    Text(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input: String,
+    },
+    Reasoning(ReasoningBlock),
+}
+
+/// Turns ConverseStream events into stream events.
+///
+/// Usage arrives in a `metadata` event after `messageStop`, so the message is completed once
+/// the metadata arrives or the stream ends.
+#[derive(Default)]
+struct ConverseStreamState {
+    pending: BTreeMap<usize, PendingBlock>,
+    content: Vec<ContentBlock>,
+    stop_reason: Option<StopReason>,
+}
+
+impl ConverseStreamState {
+    fn process(
+        &mut self,
+        frame: Frame,
+        latency: Duration,
+    ) -> Result<Vec<StreamEvent>, ModelProviderError> {
+        if frame.header(":message-type") != Some("event") {
+            let kind = frame
+                .header(":exception-type")
+                .or(frame.header(":error-code"))
+                .unwrap_or("unknown error")
+                .to_string();
+            let message = serde_json::from_slice::<ExceptionPayload>(&frame.payload)
+                .ok()
+                .and_then(|payload| payload.message)
+                .or_else(|| frame.header(":error-message").map(str::to_string))
+                .unwrap_or_default();
+
+            if is_context_overflow(&message) {
+                return Err(Error::ContextWindowOverflow.into());
+            }
+
+            return Err(BedrockError::Exception { kind, message }.into());
+        }
+
+        let mut events = Vec::new();
+        match frame.header(":event-type").unwrap_or_default() {
+            "messageStart" => events.push(StreamEvent::MessageStart {
+                role: Role::Assistant,
+            }),
+            "contentBlockStart" => {
+                let event: ContentBlockStartEvent = serde_json::from_slice(&frame.payload)?;
+                if let Some(tool_use) = event.start.and_then(|start| start.tool_use) {
+                    let index = event.content_block_index;
+                    events.push(StreamEvent::ToolUseStart {
+                        index,
+                        id: tool_use.tool_use_id.clone(),
+                        name: tool_use.name.clone(),
+                    });
+                    self.pending.insert(
+                        index,
+                        PendingBlock::ToolUse {
+                            id: tool_use.tool_use_id,
+                            name: tool_use.name,
+                            input: String::new(),
+                        },
+                    );
+                }
+            }
+            "contentBlockDelta" => {
+                let event: ContentBlockDeltaEvent = serde_json::from_slice(&frame.payload)?;
+                self.process_delta(event, &mut events)?;
+            }
+            "contentBlockStop" => {
+                let event: ContentBlockStopEvent = serde_json::from_slice(&frame.payload)?;
+                let index = event.content_block_index;
+                if let Some(pending) = self.pending.remove(&index) {
+                    let block = match pending {
+                        PendingBlock::Text(text) => ContentBlock::Text(TextBlock(text)),
+                        PendingBlock::ToolUse { id, name, input } => {
+                            ContentBlock::ToolUse(ToolUseBlock {
+                                id,
+                                name,
+                                input: if input.trim().is_empty() {
+                                    serde_json::Value::Object(Default::default())
+                                } else {
+                                    serde_json::from_str(&input).unwrap_or(serde_json::Value::Null)
+                                },
+                            })
+                        }
+                        PendingBlock::Reasoning(reasoning) => ContentBlock::Reasoning(reasoning),
+                    };
+
+                    self.content.push(block.clone());
+                    events.push(StreamEvent::ContentBlockComplete { index, block });
+                }
+            }
+            "messageStop" => {
+                let event: MessageStopEvent = serde_json::from_slice(&frame.payload)?;
+                self.stop_reason = Some(match event.stop_reason.as_str() {
+                    "tool_use" => StopReason::ToolUse,
+                    "max_tokens" => StopReason::MaxTokens,
+                    "stop_sequence" => StopReason::StopSequence,
+                    "guardrail_intervened" => StopReason::GuardrailIntervened,
+                    "content_filtered" => StopReason::ContentFiltered,
+                    "model_context_window_exceeded" => StopReason::ContextWindowExceeded,
+                    _ => StopReason::EndTurn,
+                });
+            }
+            "metadata" => {
+                let event: MetadataEvent = serde_json::from_slice(&frame.payload)?;
+                if let Some(usage) = event.usage {
+                    events.push(StreamEvent::Metadata {
+                        usage: usage.into(),
+                        latency,
+                    });
+                }
+
+                events.extend(self.complete_message());
+            }
+            _ => {}
+        }
+
+        Ok(events)
+    }
+
+    /// Completes the message if the stream ended without reporting usage.
+    fn finish(mut self) -> Option<StreamEvent> {
+        self.complete_message()
+    }
+
+    fn complete_message(&mut self) -> Option<StreamEvent> {
+        let stop_reason = self.stop_reason.take()?;
+        Some(StreamEvent::MessageComplete {
+            message: Message {
+                role: Role::Assistant,
+                content: std::mem::take(&mut self.content),
+            },
+            stop_reason,
+        })
+    }
+
+    fn process_delta(
+        &mut self,
+        event: ContentBlockDeltaEvent,
+        events: &mut Vec<StreamEvent>,
+    ) -> Result<(), ModelProviderError> {
+        let index = event.content_block_index;
+        let delta = event.delta;
+
+        if let Some(text) = delta.text {
+            match self.pending.get_mut(&index) {
+                Some(PendingBlock::Text(buffer)) => buffer.push_str(&text),
+                _ => {
+                    events.push(StreamEvent::TextStart { index });
+                    self.pending.insert(index, PendingBlock::Text(text.clone()));
+                }
+            }
+
+            events.push(StreamEvent::TextDelta { index, delta: text });
+        }
+
+        if let Some(tool_use) = delta.tool_use
+            && let Some(PendingBlock::ToolUse { input, .. }) = self.pending.get_mut(&index)
+        {
+            input.push_str(&tool_use.input);
+            events.push(StreamEvent::ToolInputDelta {
+                index,
+                delta: tool_use.input,
+            });
+        }
+
+        if let Some(reasoning) = delta.reasoning_content {
+            let redacted = reasoning
+                .redacted_content
+                .map(|content| STANDARD.decode(content))
+                .transpose()?;
+
+            if !matches!(self.pending.get(&index), Some(PendingBlock::Reasoning(_))) {
+                events.push(StreamEvent::ReasoningStart { index });
+                self.pending.insert(
+                    index,
+                    PendingBlock::Reasoning(ReasoningBlock {
+                        text: String::new(),
+                        signature: String::new(),
+                        redacted: Vec::new(),
+                    }),
+                );
+            }
+
+            if let Some(PendingBlock::Reasoning(block)) = self.pending.get_mut(&index) {
+                if let Some(text) = &reasoning.text {
+                    block.text.push_str(text);
+                }
+                if let Some(signature) = &reasoning.signature {
+                    block.signature.push_str(signature);
+                }
+                if let Some(redacted) = &redacted {
+                    block.redacted.extend_from_slice(redacted);
+                }
+            }
+
+            events.push(StreamEvent::ReasoningDelta {
+                index,
+                text: reasoning.text,
+                signature: reasoning.signature,
+                redacted,
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! AWS Signature Version 4 request signing.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::BedrockCredentials;
+
+/// Returns the headers that authenticate a request: `x-amz-date`, `x-amz-security-token` when
+/// the credentials carry a session token, and `authorization`.
+///
+/// Only the host and these `x-amz-*` headers are signed, so other headers may be added freely.
+pub(super) fn sign(
+    credentials: &BedrockCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    url: &reqwest::Url,
+    body: &[u8],
+    time: SystemTime,
+) -> Vec<(&'static str, String)> {
+    let amz_date = amz_date(time);
+    let date = &amz_date[..8];
+
+    let host = match url.port() {
+        Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let mut headers = vec![("host", host), ("x-amz-date", amz_date.clone())];
+    if let Some(token) = &credentials.session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+
+    // Services other than S3 expect every path segment to be encoded a second time. Requests
+    // never carry a query string, so the canonical query is empty.
+    let canonical_request = format!(
+        "{method}\n{}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        uri_encode(url.path(), false),
+        hex(&Sha256::digest(body)),
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = format!("AWS4{}", credentials.secret_access_key);
+    let key = hmac(key.as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, service.as_bytes());
+    let key = hmac(&key, b"aws4_request");
+    let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+         Signature={signature}",
+        credentials.access_key_id
+    );
+
+    // The HTTP client sends the host header itself.
+    headers.remove(0);
+    headers.push(("authorization", authorization));
+    headers
+}
+
+/// Percent-encodes everything except unreserved characters and, unless `encode_slash` is set,
+/// slashes.
+pub(super) fn uri_encode(value: &str, encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Formats a time as `YYYYMMDD'T'HHMMSS'Z'` in UTC.
+fn amz_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (days, secs_of_day) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Converts days since the epoch to a civil date in the proleptic Gregorian calendar.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    /// 2015-08-30T12:36:00Z, the time used throughout the AWS Signature Version 4 test suite.
+    fn suite_time() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_440_938_160)
+    }
+
+    fn suite_credentials() -> BedrockCredentials {
+        BedrockCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY")
+    }
+
+    #[test]
+    fn formats_amz_dates() {
+        assert_eq!(amz_date(suite_time()), "20150830T123600Z");
+        assert_eq!(amz_date(UNIX_EPOCH), "19700101T000000Z");
+        assert_eq!(
+            amz_date(UNIX_EPOCH + Duration::from_secs(951_825_599)),
+            "20000229T115959Z"
+        );
+    }
+
+    #[test]
+    fn signs_the_get_vanilla_suite_request() {
+        let url = reqwest::Url::parse("https://example.amazonaws.com/").unwrap();
+        let headers = sign(
+            &suite_credentials(),
+            "us-east-1",
+            "service",
+            "GET",
+            &url,
+            b"",
+            suite_time(),
+        );
+
+        assert_eq!(
+            headers,
+            [
+                ("x-amz-date", "20150830T123600Z".to_string()),
+                (
+                    "authorization",
+                    "AWS4-HMAC-SHA256 \
+                     Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                     SignedHeaders=host;x-amz-date, \
+                     Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn signs_encoded_paths_with_their_segments_encoded_again() {
+        // The model id's colon is sent as `%3A` and signed as `%253A`.
+        let url = reqwest::Url::parse(
+            "https://bedrock-runtime.us-east-1.amazonaws.com/model/amazon.nova-lite-v1%3A0/converse-stream",
+        )
+        .unwrap();
+        let credentials = suite_credentials().with_session_token("token");
+        let headers = sign(
+            &credentials,
+            "us-east-1",
+            "bedrock",
+            "POST",
+            &url,
+            br#"{"messages":[]}"#,
+            suite_time(),
+        );
+
+        assert_eq!(
+            headers,
+            [
+                ("x-amz-date", "20150830T123600Z".to_string()),
+                ("x-amz-security-token", "token".to_string()),
+                (
+                    "authorization",
+                    "AWS4-HMAC-SHA256 \
+                     Credential=AKIDEXAMPLE/20150830/us-east-1/bedrock/aws4_request, \
+                     SignedHeaders=host;x-amz-date;x-amz-security-token, \
+                     Signature=4d9db44d62de74b08a54bc86dddfb9b451e2497bca6c28786c445c13e3e718df"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn encodes_reserved_characters() {
+        assert_eq!(uri_encode("/a b/c:d~", false), "/a%20b/c%3Ad~");
+        assert_eq!(uri_encode("a/b", true), "a%2Fb");
+    }
+}
@@ -0,0 +1,698 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    message::{
+        ContentBlock, DocumentSource, ImageSource, Message, ReasoningBlock, Role, StopReason,
+        SystemPrompt, SystemPromptBlock, TextBlock, ToolResultContent, ToolUseBlock, VideoSource,
+    },
+    model::{
+        model_provider::{
+            ModelProvider, ModelProviderError, ModelProviderStream, StreamArgs, StreamEvent,
+            ToolPolicy, Usage,
+        },
+        sse::SseDecoder,
+        tool_use_id,
+    },
+    tool::ToolSpec,
+};
+
+/// The Gemini API endpoint used unless another base URL is configured.
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Errors reported by the Gemini API.
+#[derive(thiserror::Error, Debug)]
+pub enum GeminiError {
+    #[error("Server responded with {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("Server reported an error: {0}")]
+    Stream(serde_json::Value),
+}
+
+/// A model provider for Google Gemini models through the `streamGenerateContent` endpoint.
+///
+/// Images, documents and videos given as bytes are sent inline, and those given as URLs are sent
+/// as file references, which Gemini accepts for files uploaded through its Files API. Thought
+/// signatures of function calls are kept as reasoning blocks without text, and are sent back with
+/// the calls they belong to.
+#[derive(Clone, Debug)]
+pub struct GeminiModelProvider {
+    base_url: String,
+    api_key: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl GeminiModelProvider {
+    /// Creates a provider for `model`, for example `gemini-2.5-flash`.
+    pub fn new(api_key: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: DEFAULT_BASE_URL.to_string(),
+            api_key: api_key.into(),
+            model: model.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends requests to `base_url` instead of the public Gemini API.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    fn build_request(&self, messages: &[Message], args: &StreamArgs) -> GenerateRequest {
+        // Function responses are matched to their call by name, which only the tool use carries.
+        let mut tool_names = HashMap::new();
+        let mut contents = Vec::new();
+        for message in messages {
+            for block in &message.content {
+                if let ContentBlock::ToolUse(tool_use) = block {
+                    tool_names.insert(tool_use.id.as_str(), tool_use.name.as_str());
+                }
+            }
+
+            // Gemini's thought signatures arrive as reasoning blocks without text and are
+            // returned on the function call that follows them.
+            let mut thought_signature = None;
+            let mut parts = Vec::new();
+            for block in &message.content {
+                if let ContentBlock::Reasoning(reasoning) = block
+                    && reasoning.text.is_empty()
+                    && !reasoning.signature.is_empty()
+                {
+                    thought_signature = Some(reasoning.signature.clone());
+                    continue;
+                }
+
+                let Some(mut part) = part(block, &tool_names) else {
+                    continue;
+                };
+                if let ContentBlock::ToolUse(_) = block {
+                    part.thought_signature = thought_signature.take();
+                }
+                parts.push(part);
+            }
+
+            if !parts.is_empty() {
+                contents.push(Content {
+                    role: Some(match message.role {
+                        Role::User => "user",
+                        Role::Assistant => "model",
+                    }),
+                    parts,
+                });
+            }
+        }
+
+        GenerateRequest {
+            contents,
+            system_instruction: args.system_prompt.as_ref().and_then(system_instruction),
+            tools: args.tool_specs.as_ref().map(|specs| {
+                vec![Tool {
+                    function_declarations: specs.iter().map(FunctionDeclaration::from).collect(),
+                }]
+            }),
+            tool_config: args.tool_policy.as_ref().map(tool_config),
+            generation_config: GenerationConfig {
+                max_output_tokens: args.max_tokens,
+                temperature: args.temperature,
+                top_p: args.top_p,
+                stop_sequences: args.stop_sequences.clone(),
+            },
+        }
+    }
+}
+
+impl ModelProvider for GeminiModelProvider {
+    fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream {
+        let request = self.build_request(messages, args);
+        let url = format!(
+            "{}/models/{}:streamGenerateContent?alt=sse",
+            self.base_url.trim_end_matches('/'),
+            self.model
+        );
+        let api_key = self.api_key.clone();
+        let client = self.client.clone();
+
+        Box::pin(async_stream::try_stream! {
+            let started = Instant::now();
+            let mut response = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("x-goog-api-key", api_key)
+                .body(serde_json::to_vec(&request)?)
+                .send()
+                .await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await?;
+                let error: ModelProviderError = if is_context_overflow(&body) {
+                    Error::ContextWindowOverflow.into()
+                } else {
+                    GeminiError::Status { status, body }.into()
+                };
+
+                Err(error)?;
+                return;
+            }
+
+            let mut decoder = SseDecoder::default();
+            let mut state = GenerateStreamState::default();
+            let mut done = false;
+
+            while !done {
+                let data = match response.chunk().await? {
+                    Some(chunk) => decoder.push(&chunk),
+                    None => {
+                        done = true;
+                        decoder.finish().into_iter().collect()
+                    }
+                };
+
+                for data in data {
+                    let chunk: GenerateChunk = serde_json::from_str(&data)?;
+                    if let Some(error) = chunk.error {
+                        Err(GeminiError::Stream(error))?;
+                        return;
+                    }
+
+                    for event in state.process(chunk) {
+                        yield event;
+                    }
+                }
+            }
+
+            for event in state.finish(started.elapsed()) {
+                yield event;
+            }
+        })
+    }
+}
+
+/// Returns true if an error response says the prompt does not fit the model's context window.
+fn is_context_overflow(body: &str) -> bool {
+    body.contains("exceeds the maximum number of tokens")
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateRequest {
+    contents: Vec<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<Tool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_config: Option<ToolConfig>,
+    generation_config: GenerationConfig,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_sequences: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct Content {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'static str>,
+    parts: Vec<Part>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct Part {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// Set on parts that carry the model's thoughts rather than its answer.
+    #[serde(default, skip_serializing)]
+    thought: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inline_data: Option<Blob>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_data: Option<FileData>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_call: Option<FunctionCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    function_response: Option<FunctionResponse>,
+    /// An opaque encoding of the model's thoughts, which must be sent back with the part it
+    /// came with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thought_signature: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Blob {
+    mime_type: String,
+    data: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FileData {
+    mime_type: String,
+    file_uri: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionCall {
+    #[serde(default, skip_serializing)]
+    id: Option<String>,
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionResponse {
+    name: String,
+    response: serde_json::Value,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Tool {
+    function_declarations: Vec<FunctionDeclaration>,
+}
+
+/// A function the model may call. Its schema is sent as `parametersJsonSchema`, which accepts
+/// full JSON Schema rather than the OpenAPI subset of `parameters`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FunctionDeclaration {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    parameters_json_schema: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<&ToolSpec> for FunctionDeclaration {
+    fn from(spec: &ToolSpec) -> Self {
+        FunctionDeclaration {
+            name: spec.name.clone(),
+            description: spec.description.clone(),
+            parameters_json_schema: spec.input_schema.clone(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolConfig {
+    function_calling_config: FunctionCallingConfig,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct FunctionCallingConfig {
+    mode: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allowed_function_names: Option<Vec<String>>,
+}
+
+fn tool_config(policy: &ToolPolicy) -> ToolConfig {
+    let (mode, allowed_function_names) = match policy {
+        ToolPolicy::Auto => ("AUTO", None),
+        ToolPolicy::None => ("NONE", None),
+        ToolPolicy::Required => ("ANY", None),
+        ToolPolicy::Specific { name } => ("ANY", Some(vec![name.clone()])),
+    };
+
+    ToolConfig {
+        function_calling_config: FunctionCallingConfig {
+            mode,
+            allowed_function_names,
+        },
+    }
+}
+
+fn system_instruction(prompt: &SystemPrompt) -> Option<Content> {
+    let parts: Vec<Part> = match prompt {
+        SystemPrompt::Text(text) => vec![text_part(text.clone())],
+        SystemPrompt::Structured(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                SystemPromptBlock::Text(TextBlock(text)) => Some(text_part(text.clone())),
+                _ => None,
+            })
+            .collect(),
+    };
+
+    (!parts.is_empty()).then_some(Content { role: None, parts })
+}
+
+fn text_part(text: String) -> Part {
+    Part {
+        text: Some(text),
+        ..Default::default()
+    }
+}
+
+fn inline_part(mime_type: &str, bytes: &[u8]) -> Part {
+    Part {
+        inline_data: Some(Blob {
+            mime_type: mime_type.to_string(),
+            data: STANDARD.encode(bytes),
+        }),
+        ..Default::default()
+    }
+}
+
+fn file_part(mime_type: &str, url: &str) -> Part {
+    Part {
+        file_data: Some(FileData {
+            mime_type: mime_type.to_string(),
+            file_uri: url.to_string(),
+        }),
+        ..Default::default()
+    }
+}
+
+/// Converts a content block into a part. Reasoning, guard content and cache points have no
+/// Gemini part of their own and are skipped.
+fn part(block: &ContentBlock, tool_names: &HashMap<&str, &str>) -> Option<Part> {
+    match block {
+        ContentBlock::Text(TextBlock(text)) => Some(text_part(text.clone())),
+        ContentBlock::Image(image) => Some(match &image.source {
+            ImageSource::Bytes(bytes) => inline_part(image.format.mime_type(), bytes),
+            ImageSource::Url(url) => file_part(image.format.mime_type(), url),
+        }),
+        ContentBlock::Document(document) => {
+            let mime_type = document.format.mime_type();
+            match &document.source {
+                DocumentSource::Bytes(bytes) => Some(inline_part(mime_type, bytes)),
+                DocumentSource::Text(text) => Some(text_part(text.clone())),
+                DocumentSource::Structured(blocks) => Some(text_part(
+                    blocks
+                        .iter()
+                        .map(|b| b.0.as_str())
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                )),
+                DocumentSource::Url(url) => Some(file_part(mime_type, url)),
+            }
+        }
+        ContentBlock::Video(video) => match &video.source {
+            VideoSource::Bytes(bytes) => Some(inline_part(video.format.mime_type(), bytes)),
+        },
+        ContentBlock::ToolUse(tool_use) => Some(Part {
+            function_call: Some(FunctionCall {
+                id: None,
+                name: tool_use.name.clone(),
+                args: tool_use.input.clone(),
+            }),
+            ..Default::default()
+        }),
+        ContentBlock::ToolResult(result) => {
+            let response = match &result.content {
+                Ok(items) => serde_json::json!({ "output": tool_result_value(items) }),
+                Err(items) => serde_json::json!({ "error": tool_result_value(items) }),
+            };
+
+            Some(Part {
+                function_response: Some(FunctionResponse {
+                    name: tool_names
+                        .get(result.id.as_str())
+                        .map_or_else(|| result.id.clone(), |n| n.to_string()),
+                    response,
+                }),
+                ..Default::default()
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Converts tool result content into a JSON value, unwrapping a lone item.
+fn tool_result_value(items: &[ToolResultContent]) -> serde_json::Value {
+    let mut values: Vec<serde_json::Value> = items
+        .iter()
+        .map(|item| match item {
+            ToolResultContent::Text(TextBlock(text)) => text.clone().into(),
+            ToolResultContent::Json(json) => json.0.clone(),
+            ToolResultContent::Image(_) => "[image omitted: not supported by this provider]".into(),
+            ToolResultContent::Document(document) => format!(
+                "[document {} omitted: not supported by this provider]",
+                document.name
+            )
+            .into(),
+        })
+        .collect();
+
+    match values.len() {
+        1 => values.remove(0),
+        _ => values.into(),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateChunk {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    prompt_feedback: Option<PromptFeedback>,
+    usage_metadata: Option<UsageMetadata>,
+    error: Option<serde_json::Value>,
+}
+
+/// Token counts so far, repeated with growing totals on every chunk.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    cached_content_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    #[serde(default)]
+    thoughts_token_count: u64,
+}
+
+impl From<UsageMetadata> for Usage {
+    fn from(usage: UsageMetadata) -> Self {
+        // Prompt tokens include those read from cached content.
+        Usage {
+            input_tokens: usage
+                .prompt_token_count
+                .saturating_sub(usage.cached_content_token_count),
+            output_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+            cache_read_tokens: usage.cached_content_token_count,
+            cache_write_tokens: 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Candidate {
+    content: Option<CandidateContent>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<Part>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    block_reason: Option<String>,
+}
+
+/// Turns response chunks into stream events.
+///
+/// Gemini streams text incrementally but delivers each function call whole, so a tool use starts
+/// and completes within a single chunk. A text block ends when a function call arrives.
+#[derive(Default)]
+struct GenerateStreamState {
+    started: bool,
+    next_index: usize,
+    text: Option<(usize, String)>,
+    content: Vec<ContentBlock>,
+    finish_reason: Option<String>,
+    usage: Option<Usage>,
+}
+
+impl GenerateStreamState {
+    fn process(&mut self, chunk: GenerateChunk) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        if !self.started {
+            self.started = true;
+            events.push(StreamEvent::MessageStart {
+                role: Role::Assistant,
+            });
+        }
+
+        if let Some(usage) = chunk.usage_metadata {
+            self.usage = Some(usage.into());
+        }
+
+        // A blocked prompt produces no candidates.
+        if let Some(reason) = chunk.prompt_feedback.and_then(|f| f.block_reason) {
+            self.finish_reason = Some(reason);
+        }
+
+        // Only the first candidate is used; requests never ask for more.
+        if let Some(candidate) = chunk.candidates.into_iter().next() {
+            let parts = candidate.content.map(|c| c.parts).unwrap_or_default();
+            for part in parts.into_iter().filter(|p| !p.thought) {
+                if let Some(text) = part.text.filter(|t| !t.is_empty()) {
+                    self.push_text(text, &mut events);
+                }
+
+                if let Some(call) = part.function_call {
+                    if let Some(signature) = part.thought_signature {
+                        self.push_thought_signature(signature, &mut events);
+                    }
+                    self.push_function_call(call, &mut events);
+                }
+            }
+
+            if let Some(reason) = candidate.finish_reason {
+                self.finish_reason = Some(reason);
+            }
+        }
+
+        events
+    }
+
+    fn push_text(&mut self, text: String, events: &mut Vec<StreamEvent>) {
+        let index = match &mut self.text {
+            Some((index, buffer)) => {
+                buffer.push_str(&text);
+                *index
+            }
+            None => {
+                let index = self.next_index;
+                self.next_index += 1;
+                self.text = Some((index, text.clone()));
+                events.push(StreamEvent::TextStart { index });
+                index
+            }
+        };
+
+        events.push(StreamEvent::TextDelta { index, delta: text });
+    }
+
+    /// Keeps the signature of a function call as a reasoning block without text, so it can be
+    /// returned with the call in the next request.
+    fn push_thought_signature(&mut self, signature: String, events: &mut Vec<StreamEvent>) {
+        self.complete_text(events);
+
+        let index = self.next_index;
+        self.next_index += 1;
+
+        events.push(StreamEvent::ReasoningStart { index });
+        events.push(StreamEvent::ReasoningDelta {
+            index,
+            text: None,
+            signature: Some(signature.clone()),
+            redacted: None,
+        });
+
+        let block = ContentBlock::Reasoning(ReasoningBlock {
+            text: String::new(),
+            signature,
+            redacted: Vec::new(),
+        });
+        self.content.push(block.clone());
+        events.push(StreamEvent::ContentBlockComplete { index, block });
+    }
+
+    fn push_function_call(&mut self, call: FunctionCall, events: &mut Vec<StreamEvent>) {
+        self.complete_text(events);
+
+        let index = self.next_index;
+        self.next_index += 1;
+        let tool_use = ToolUseBlock {
+            id: call.id.unwrap_or_else(tool_use_id),
+            name: call.name,
+            input: match call.args {
+                serde_json::Value::Null => serde_json::Value::Object(Default::default()),
+                args => args,
+            },
+        };
+
+        events.push(StreamEvent::ToolUseStart {
+            index,
+            id: tool_use.id.clone(),
+            name: tool_use.name.clone(),
+        });
+        events.push(StreamEvent::ToolInputDelta {
+            index,
+            delta: tool_use.input.to_string(),
+        });
+
+        let block = ContentBlock::ToolUse(tool_use);
+        self.content.push(block.clone());
+        events.push(StreamEvent::ContentBlockComplete { index, block });
+    }
+
+    fn complete_text(&mut self, events: &mut Vec<StreamEvent>) {
+        if let Some((index, text)) = self.text.take() {
+            let block = ContentBlock::Text(TextBlock(text));
+            self.content.push(block.clone());
+            events.push(StreamEvent::ContentBlockComplete { index, block });
+        }
+    }
+
+    /// Completes the open text block and the message. Nothing is emitted if the server never
+    /// reported why generation finished, since the response was then cut short.
+    fn finish(mut self, latency: Duration) -> Vec<StreamEvent> {
+        let Some(finish_reason) = self.finish_reason.take() else {
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+        self.complete_text(&mut events);
+
+        let has_tool_use = self
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse(_)));
+
+        let stop_reason = match finish_reason.as_str() {
+            "MAX_TOKENS" => StopReason::MaxTokens,
+            "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII"
+            | "IMAGE_SAFETY" | "OTHER" => StopReason::ContentFiltered,
+            _ if has_tool_use => StopReason::ToolUse,
+            _ => StopReason::EndTurn,
+        };
+
+        if let Some(usage) = self.usage {
+            events.push(StreamEvent::Metadata { usage, latency });
+        }
+
+        events.push(StreamEvent::MessageComplete {
+            message: Message {
+                role: Role::Assistant,
+                content: self.content,
+            },
+            stop_reason,
+        });
+
+        events
+    }
+}
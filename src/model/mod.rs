@@ -1,2 +1,25 @@
 pub mod anthropic;
+pub mod bedrock;
+pub mod gemini;
 pub mod model_provider;
+pub mod ollama;
+pub mod openai;
+mod sse;
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Returns a new id for a tool use from a provider that does not assign ids itself.
+///
+/// Ids combine the time with a counter, so they stay unique across the turns of a conversation
+/// and across conversations restored from a session.
+pub(crate) fn tool_use_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    format!("call_{nanos:x}_{}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
@@ -1,15 +1,21 @@
-use std::pin::Pin;
+use std::{ops::AddAssign, pin::Pin, time::Duration};
 
 use futures::Stream;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    message::{ContentBlock, Message, Role, StopReason, SystemPrompt},
+    message::{CachePointBlock, ContentBlock, Message, Role, StopReason, SystemPrompt},
     tool::ToolSpec,
 };
 
 /// Events emitted by a model during streaming response generation.
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum StreamEvent {
     /// Response generation has started.
@@ -33,10 +39,17 @@ pub enum StreamEvent {
         index: usize,
         text: Option<String>,
         signature: Option<String>,
+        #[cfg_attr(
+            feature = "serde",
+            serde(with = "crate::message::base64_bytes::option")
+        )]
         redacted: Option<Vec<u8>>,
     },
     /// A content block has completed.
     ContentBlockComplete { index: usize, block: ContentBlock },
+    /// Token usage for the model call and the time since the request was sent. Emitted before
+    /// `MessageComplete` when the provider reports usage.
+    Metadata { usage: Usage, latency: Duration },
     /// Response generation has finished.
     MessageComplete {
         message: Message,
@@ -44,6 +57,58 @@ pub enum StreamEvent {
     },
 }
 
+/// Token counts reported for a model call.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Usage {
+    /// Input tokens that were neither read from nor written to the prompt cache.
+    pub input_tokens: u64,
+    /// Generated tokens, including reasoning.
+    pub output_tokens: u64,
+    /// Input tokens read from the prompt cache.
+    pub cache_read_tokens: u64,
+    /// Input tokens written to the prompt cache.
+    pub cache_write_tokens: u64,
+}
+
+impl Usage {
+    /// Returns every input and output token, cached or not.
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens + self.output_tokens + self.cache_read_tokens + self.cache_write_tokens
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cache_write_tokens += other.cache_write_tokens;
+    }
+}
+
+/// Prices for a model in US dollars per million tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+    pub cache_read: f64,
+    pub cache_write: f64,
+}
+
+impl ModelPricing {
+    /// Returns the cost in US dollars of the tokens in `usage`.
+    pub fn cost(&self, usage: &Usage) -> f64 {
+        let dollars = usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_read_tokens as f64 * self.cache_read
+            + usage.cache_write_tokens as f64 * self.cache_write;
+
+        dollars / 1_000_000.0
+    }
+}
+
 /// Error type for model operations.
 pub type ModelProviderError = Box<dyn std::error::Error + Send + Sync>;
 
@@ -53,6 +118,10 @@ pub type ModelProviderStream =
 
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "value", rename_all = "snake_case")
+)]
 #[non_exhaustive]
 pub enum ToolPolicy {
     /// Let the model decide which tools to use.
@@ -74,12 +143,22 @@ pub struct StreamArgs {
     pub system_prompt: Option<SystemPrompt>,
     pub tool_policy: Option<ToolPolicy>,
     pub tool_specs: Option<Vec<ToolSpec>>,
+    /// Whether the model may call several tools in one response. Providers allow it by default
+    /// and ignore the setting when they cannot control it.
+    pub parallel_tool_use: Option<bool>,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
     pub top_p: Option<f32>,
     pub stop_sequences: Option<Vec<String>>,
+    /// Places a cache point after the tool definitions.
+    pub tool_cache_point: Option<CachePointBlock>,
 }
 
 pub trait ModelProvider: Send + Sync {
     fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream;
+
+    /// Returns the prices of the model this provider calls, if known.
+    fn pricing(&self) -> Option<ModelPricing> {
+        None
+    }
 }
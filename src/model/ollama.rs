@@ -0,0 +1,463 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{
+        ContentBlock, DocumentSource, ImageSource, Message, Role, StopReason, SystemPrompt,
+        SystemPromptBlock, TextBlock, ToolResultContent, ToolUseBlock,
+    },
+    model::{
+        model_provider::{
+            ModelProvider, ModelProviderStream, StreamArgs, StreamEvent, ToolPolicy, Usage,
+        },
+        tool_use_id,
+    },
+    tool::ToolSpec,
+};
+
+/// Errors reported by an Ollama server.
+#[derive(thiserror::Error, Debug)]
+pub enum OllamaError {
+    #[error("Server responded with {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("Server reported an error: {0}")]
+    Stream(String),
+}
+
+/// A model provider for Ollama's native `/api/chat` endpoint.
+///
+/// Ollama cannot force tool use, so [`ToolPolicy::Required`] and [`ToolPolicy::Specific`] offer
+/// the tools without requiring them. [`ToolPolicy::None`] withholds the tools from the model.
+#[derive(Clone, Debug)]
+pub struct OllamaModelProvider {
+    base_url: String,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl OllamaModelProvider {
+    /// Creates a provider for `model` served at `base_url`, for example `http://localhost:11434`.
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            model: model.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn build_request(&self, messages: &[Message], args: &StreamArgs) -> ChatRequest {
+        let mut chat_messages = Vec::new();
+        if let Some(system_prompt) = &args.system_prompt {
+            chat_messages.push(ChatMessage {
+                role: "system",
+                content: system_prompt_text(system_prompt),
+                ..Default::default()
+            });
+        }
+
+        // Tool results are matched to their tool by name, which only the tool use carries.
+        let mut tool_names = HashMap::new();
+        for message in messages {
+            for block in &message.content {
+                if let ContentBlock::ToolUse(tool_use) = block {
+                    tool_names.insert(tool_use.id.as_str(), tool_use.name.as_str());
+                }
+            }
+
+            push_chat_messages(&mut chat_messages, message, &tool_names);
+        }
+
+        let tools = match args.tool_policy {
+            Some(ToolPolicy::None) => None,
+            _ => args
+                .tool_specs
+                .as_ref()
+                .map(|specs| specs.iter().map(ChatTool::from).collect()),
+        };
+
+        ChatRequest {
+            model: self.model.clone(),
+            messages: chat_messages,
+            stream: true,
+            tools,
+            options: ChatOptions {
+                temperature: args.temperature,
+                top_p: args.top_p,
+                stop: args.stop_sequences.clone(),
+                num_predict: args.max_tokens,
+            },
+        }
+    }
+}
+
+impl ModelProvider for OllamaModelProvider {
+    fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream {
+        let request = self.build_request(messages, args);
+        let url = format!("{}/api/chat", self.base_url.trim_end_matches('/'));
+        let client = self.client.clone();
+
+        Box::pin(async_stream::try_stream! {
+            let started = Instant::now();
+            let mut response = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&request)?)
+                .send()
+                .await?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await?;
+                Err(OllamaError::Status { status, body })?;
+                return;
+            }
+
+            let mut buffer = Vec::new();
+            let mut state = ChatStreamState::default();
+            let mut done = false;
+
+            while !done {
+                match response.chunk().await? {
+                    Some(chunk) => buffer.extend_from_slice(&chunk),
+                    None => {
+                        done = true;
+                        buffer.push(b'\n');
+                    }
+                }
+
+                // Each line of the body is a complete JSON chunk.
+                while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+                    let line: Vec<u8> = buffer.drain(..=end).collect();
+                    if line.iter().all(u8::is_ascii_whitespace) {
+                        continue;
+                    }
+
+                    let chunk: ChatChunk = serde_json::from_slice(&line)?;
+                    if let Some(error) = chunk.error {
+                        Err(OllamaError::Stream(error))?;
+                        return;
+                    }
+
+                    for event in state.process(chunk, started.elapsed()) {
+                        yield event;
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ChatTool>>,
+    options: ChatOptions,
+}
+
+#[derive(Serialize)]
+struct ChatOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+#[derive(Serialize, Default)]
+struct ChatMessage {
+    role: &'static str,
+    content: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<ChatToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatToolCall {
+    function: ChatFunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChatFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct ChatTool {
+    r#type: &'static str,
+    function: ChatFunction,
+}
+
+#[derive(Serialize)]
+struct ChatFunction {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<&ToolSpec> for ChatTool {
+    fn from(spec: &ToolSpec) -> Self {
+        ChatTool {
+            r#type: "function",
+            function: ChatFunction {
+                name: spec.name.clone(),
+                description: spec.description.clone(),
+                parameters: spec.input_schema.clone(),
+            },
+        }
+    }
+}
+
+fn system_prompt_text(prompt: &SystemPrompt) -> String {
+    match prompt {
+        SystemPrompt::Text(text) => text.clone(),
+        SystemPrompt::Structured(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                SystemPromptBlock::Text(TextBlock(text)) => Some(text.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Converts a message into chat messages, with each tool result becoming its own `tool` message.
+fn push_chat_messages(
+    chat_messages: &mut Vec<ChatMessage>,
+    message: &Message,
+    tool_names: &HashMap<&str, &str>,
+) {
+    let mut texts = Vec::new();
+    let mut images = Vec::new();
+    let mut tool_calls = Vec::new();
+
+    for block in &message.content {
+        match block {
+            ContentBlock::Text(TextBlock(text)) => texts.push(text.clone()),
+            ContentBlock::Image(image) => match &image.source {
+                ImageSource::Bytes(bytes) => images.push(STANDARD.encode(bytes)),
+                _ => texts.push("[image omitted: not supported by this provider]".to_string()),
+            },
+            ContentBlock::Document(document) => texts.push(match &document.source {
+                DocumentSource::Text(text) => text.clone(),
+                _ => format!(
+                    "[document {} omitted: not supported by this provider]",
+                    document.name
+                ),
+            }),
+            ContentBlock::Video(_) => {
+                texts.push("[video omitted: not supported by this provider]".to_string())
+            }
+            ContentBlock::ToolUse(tool_use) => tool_calls.push(ChatToolCall {
+                function: ChatFunctionCall {
+                    name: tool_use.name.clone(),
+                    arguments: tool_use.input.clone(),
+                },
+            }),
+            ContentBlock::ToolResult(result) => {
+                let items = result.content.as_ref().unwrap_or_else(|e| e);
+                chat_messages.push(ChatMessage {
+                    role: "tool",
+                    content: tool_result_text(items),
+                    tool_name: tool_names.get(result.id.as_str()).map(|n| n.to_string()),
+                    ..Default::default()
+                });
+            }
+            _ => {}
+        }
+    }
+
+    if texts.is_empty() && images.is_empty() && tool_calls.is_empty() {
+        return;
+    }
+
+    chat_messages.push(ChatMessage {
+        role: match message.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        },
+        content: texts.join("\n"),
+        images,
+        tool_calls,
+        tool_name: None,
+    });
+}
+
+fn tool_result_text(items: &[ToolResultContent]) -> String {
+    items
+        .iter()
+        .map(|item| match item {
+            ToolResultContent::Text(TextBlock(text)) => text.clone(),
+            ToolResultContent::Json(json) => json.0.to_string(),
+            ToolResultContent::Image(_) => {
+                "[image omitted: not supported by this provider]".to_string()
+            }
+            ToolResultContent::Document(document) => format!(
+                "[document {} omitted: not supported by this provider]",
+                document.name
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    message: Option<ChunkMessage>,
+    #[serde(default)]
+    done: bool,
+    done_reason: Option<String>,
+    /// Number of prompt tokens, reported with the final chunk.
+    prompt_eval_count: Option<u64>,
+    /// Number of generated tokens, reported with the final chunk.
+    eval_count: Option<u64>,
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkMessage {
+    #[serde(default)]
+    content: String,
+    tool_calls: Option<Vec<ChatToolCall>>,
+}
+
+/// Turns chat chunks into stream events.
+///
+/// Ollama streams text incrementally but delivers each tool call whole, so a tool use starts and
+/// completes within a single chunk. A text block ends when a tool call arrives.
+#[derive(Default)]
+struct ChatStreamState {
+    started: bool,
+    next_index: usize,
+    text: Option<(usize, String)>,
+    content: Vec<ContentBlock>,
+}
+
+impl ChatStreamState {
+    fn process(&mut self, chunk: ChatChunk, latency: Duration) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        if !self.started {
+            self.started = true;
+            events.push(StreamEvent::MessageStart {
+                role: Role::Assistant,
+            });
+        }
+
+        if let Some(message) = chunk.message {
+            if !message.content.is_empty() {
+                let index = match &mut self.text {
+                    Some((index, buffer)) => {
+                        buffer.push_str(&message.content);
+                        *index
+                    }
+                    None => {
+                        let index = self.next_index;
+                        self.next_index += 1;
+                        self.text = Some((index, message.content.clone()));
+                        events.push(StreamEvent::TextStart { index });
+                        index
+                    }
+                };
+
+                events.push(StreamEvent::TextDelta {
+                    index,
+                    delta: message.content,
+                });
+            }
+
+            for call in message.tool_calls.unwrap_or_default() {
+                self.complete_text(&mut events);
+
+                let index = self.next_index;
+                self.next_index += 1;
+                let tool_use = ToolUseBlock {
+                    id: tool_use_id(),
+                    name: call.function.name,
+                    input: match call.function.arguments {
+                        serde_json::Value::Null => serde_json::Value::Object(Default::default()),
+                        arguments => arguments,
+                    },
+                };
+
+                events.push(StreamEvent::ToolUseStart {
+                    index,
+                    id: tool_use.id.clone(),
+                    name: tool_use.name.clone(),
+                });
+                events.push(StreamEvent::ToolInputDelta {
+                    index,
+                    delta: tool_use.input.to_string(),
+                });
+
+                let block = ContentBlock::ToolUse(tool_use);
+                self.content.push(block.clone());
+                events.push(StreamEvent::ContentBlockComplete { index, block });
+            }
+        }
+
+        if chunk.done {
+            self.complete_text(&mut events);
+
+            let has_tool_use = self
+                .content
+                .iter()
+                .any(|block| matches!(block, ContentBlock::ToolUse(_)));
+
+            let stop_reason = match chunk.done_reason.as_deref() {
+                Some("length") => StopReason::MaxTokens,
+                _ if has_tool_use => StopReason::ToolUse,
+                _ => StopReason::EndTurn,
+            };
+
+            if chunk.prompt_eval_count.is_some() || chunk.eval_count.is_some() {
+                events.push(StreamEvent::Metadata {
+                    usage: Usage {
+                        input_tokens: chunk.prompt_eval_count.unwrap_or_default(),
+                        output_tokens: chunk.eval_count.unwrap_or_default(),
+                        ..Default::default()
+                    },
+                    latency,
+                });
+            }
+
+            events.push(StreamEvent::MessageComplete {
+                message: Message {
+                    role: Role::Assistant,
+                    content: std::mem::take(&mut self.content),
+                },
+                stop_reason,
+            });
+        }
+
+        events
+    }
+
+    fn complete_text(&mut self, events: &mut Vec<StreamEvent>) {
+        if let Some((index, text)) = self.text.take() {
+            let block = ContentBlock::Text(TextBlock(text));
+            self.content.push(block.clone());
+            events.push(StreamEvent::ContentBlockComplete { index, block });
+        }
+    }
+}
@@ -0,0 +1,661 @@
+use std::time::{Duration, Instant};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::Error,
+    message::{
+        ContentBlock, DocumentSource, ImageSource, Message, Role, StopReason, SystemPrompt,
+        SystemPromptBlock, TextBlock, ToolResultContent, ToolUseBlock,
+    },
+    model::{
+        model_provider::{
+            ModelProvider, ModelProviderError, ModelProviderStream, StreamArgs, StreamEvent,
+            ToolPolicy, Usage,
+        },
+        sse::SseDecoder,
+        tool_use_id,
+    },
+    tool::ToolSpec,
+};
+
+/// Errors reported by an OpenAI-compatible server.
+#[derive(thiserror::Error, Debug)]
+pub enum OpenAiError {
+    #[error("Server responded with {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+    #[error("Server reported an error: {0}")]
+    Stream(serde_json::Value),
+}
+
+/// A model provider for servers that implement the OpenAI Chat Completions API, such as OpenAI,
+/// vLLM and llama.cpp.
+#[derive(Clone, Debug)]
+pub struct OpenAiModelProvider {
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    client: reqwest::Client,
+}
+
+impl OpenAiModelProvider {
+    /// Creates a provider for `model` served at `base_url`, for example `http://localhost:8000/v1`.
+    pub fn new(base_url: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            base_url: base_url.into(),
+            api_key: None,
+            model: model.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends `api_key` as a bearer token with every request.
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    fn build_request(&self, messages: &[Message], args: &StreamArgs) -> ChatRequest {
+        let mut chat_messages = Vec::new();
+        if let Some(system_prompt) = &args.system_prompt {
+            chat_messages.push(ChatMessage::System {
+                content: system_prompt_text(system_prompt),
+            });
+        }
+
+        for message in messages {
+            push_chat_messages(&mut chat_messages, message);
+        }
+
+        ChatRequest {
+            model: self.model.clone(),
+            messages: chat_messages,
+            stream: true,
+            stream_options: StreamOptions {
+                include_usage: true,
+            },
+            max_tokens: args.max_tokens,
+            temperature: args.temperature,
+            top_p: args.top_p,
+            stop: args.stop_sequences.clone(),
+            tools: args
+                .tool_specs
+                .as_ref()
+                .map(|specs| specs.iter().map(ChatTool::from).collect()),
+            tool_choice: args.tool_policy.as_ref().map(tool_choice),
+            // OpenAI rejects the setting when no tools are offered.
+            parallel_tool_calls: args.parallel_tool_use.filter(|_| args.tool_specs.is_some()),
+        }
+    }
+}
+
+impl ModelProvider for OpenAiModelProvider {
+    fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream {
+        let request = self.build_request(messages, args);
+        let url = format!("{}/chat/completions", self.base_url.trim_end_matches('/'));
+        let api_key = self.api_key.clone();
+        let client = self.client.clone();
+
+        Box::pin(async_stream::try_stream! {
+            let started = Instant::now();
+            let mut builder = client
+                .post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(&request)?);
+            if let Some(api_key) = api_key {
+                builder = builder.bearer_auth(api_key);
+            }
+
+            let mut response = builder.send().await?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await?;
+                let error: ModelProviderError = if is_context_overflow(&body) {
+                    Error::ContextWindowOverflow.into()
+                } else {
+                    OpenAiError::Status { status, body }.into()
+                };
+
+                Err(error)?;
+                return;
+            }
+
+            let mut decoder = SseDecoder::default();
+            let mut state = ChatStreamState::default();
+            let mut done = false;
+
+            while !done {
+                let data = match response.chunk().await? {
+                    Some(chunk) => decoder.push(&chunk),
+                    None => {
+                        done = true;
+                        decoder.finish().into_iter().collect()
+                    }
+                };
+
+                for data in data {
+                    if data == "[DONE]" {
+                        done = true;
+                        break;
+                    }
+
+                    let chunk: ChatChunk = serde_json::from_str(&data)?;
+                    if let Some(error) = chunk.error {
+                        Err(OpenAiError::Stream(error))?;
+                        return;
+                    }
+
+                    for event in state.process(chunk) {
+                        yield event;
+                    }
+                }
+            }
+
+            for event in state.finish(started.elapsed()) {
+                yield event;
+            }
+        })
+    }
+}
+
+/// Returns true if an error response says the prompt does not fit the model's context window.
+fn is_context_overflow(body: &str) -> bool {
+    body.contains("context_length_exceeded") || body.contains("maximum context length")
+}
+
+#[derive(Serialize)]
+struct ChatRequest {
+    model: String,
+    messages: Vec<ChatMessage>,
+    stream: bool,
+    stream_options: StreamOptions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<ChatTool>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    /// Asks for a final chunk that reports the token usage of the request.
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "role", rename_all = "lowercase")]
+enum ChatMessage {
+    System {
+        content: String,
+    },
+    User {
+        content: UserContent,
+    },
+    Assistant {
+        content: Option<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        tool_calls: Vec<ChatToolCall>,
+    },
+    Tool {
+        tool_call_id: String,
+        content: String,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum UserContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Serialize)]
+struct ChatToolCall {
+    id: String,
+    r#type: &'static str,
+    function: ChatFunctionCall,
+}
+
+#[derive(Serialize)]
+struct ChatFunctionCall {
+    name: String,
+    arguments: String,
+}
+
+#[derive(Serialize)]
+struct ChatTool {
+    r#type: &'static str,
+    function: ChatFunction,
+}
+
+#[derive(Serialize)]
+struct ChatFunction {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    parameters: serde_json::Map<String, serde_json::Value>,
+}
+
+impl From<&ToolSpec> for ChatTool {
+    fn from(spec: &ToolSpec) -> Self {
+        ChatTool {
+            r#type: "function",
+            function: ChatFunction {
+                name: spec.name.clone(),
+                description: spec.description.clone(),
+                parameters: spec.input_schema.clone(),
+            },
+        }
+    }
+}
+
+fn tool_choice(policy: &ToolPolicy) -> serde_json::Value {
+    match policy {
+        ToolPolicy::Auto => "auto".into(),
+        ToolPolicy::None => "none".into(),
+        ToolPolicy::Required => "required".into(),
+        ToolPolicy::Specific { name } => serde_json::json!({
+            "type": "function",
+            "function": { "name": name },
+        }),
+    }
+}
+
+fn system_prompt_text(prompt: &SystemPrompt) -> String {
+    match prompt {
+        SystemPrompt::Text(text) => text.clone(),
+        SystemPrompt::Structured(blocks) => blocks
+            .iter()
+            .filter_map(|b| match b {
+                SystemPromptBlock::Text(TextBlock(text)) => Some(text.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Converts a message into chat messages.
+///
+/// Tool results become `tool` messages, which must directly follow the assistant message that
+/// requested them, so they are emitted before the rest of a user message's content.
+fn push_chat_messages(chat_messages: &mut Vec<ChatMessage>, message: &Message) {
+    match message.role {
+        Role::User => {
+            let mut parts = Vec::new();
+            for block in &message.content {
+                match block {
+                    ContentBlock::ToolResult(result) => chat_messages.push(ChatMessage::Tool {
+                        tool_call_id: result.id.clone(),
+                        content: tool_result_text(result.content.as_ref().unwrap_or_else(|e| e)),
+                    }),
+                    block => parts.extend(content_part(block)),
+                }
+            }
+
+            if parts.is_empty() {
+                return;
+            }
+
+            let content = if parts.iter().all(|p| matches!(p, ContentPart::Text { .. })) {
+                let texts: Vec<String> = parts
+                    .into_iter()
+                    .filter_map(|p| match p {
+                        ContentPart::Text { text } => Some(text),
+                        ContentPart::ImageUrl { .. } => None,
+                    })
+                    .collect();
+                UserContent::Text(texts.join("\n"))
+            } else {
+                UserContent::Parts(parts)
+            };
+
+            chat_messages.push(ChatMessage::User { content });
+        }
+        Role::Assistant => {
+            let mut texts = Vec::new();
+            let mut tool_calls = Vec::new();
+            for block in &message.content {
+                match block {
+                    ContentBlock::Text(TextBlock(text)) => texts.push(text.as_str()),
+                    ContentBlock::ToolUse(tool_use) => tool_calls.push(ChatToolCall {
+                        id: tool_use.id.clone(),
+                        r#type: "function",
+                        function: ChatFunctionCall {
+                            name: tool_use.name.clone(),
+                            arguments: tool_use.input.to_string(),
+                        },
+                    }),
+                    _ => {}
+                }
+            }
+
+            chat_messages.push(ChatMessage::Assistant {
+                content: (!texts.is_empty()).then(|| texts.join("\n")),
+                tool_calls,
+            });
+        }
+    }
+}
+
+fn content_part(block: &ContentBlock) -> Option<ContentPart> {
+    match block {
+        ContentBlock::Text(TextBlock(text)) => Some(ContentPart::Text { text: text.clone() }),
+        ContentBlock::Image(image) => {
+            let url = match &image.source {
+                ImageSource::Bytes(bytes) => format!(
+                    "data:{};base64,{}",
+                    image.format.mime_type(),
+                    STANDARD.encode(bytes)
+                ),
+                ImageSource::Url(url) => url.clone(),
+            };
+
+            Some(ContentPart::ImageUrl {
+                image_url: ImageUrl { url },
+            })
+        }
+        ContentBlock::Document(document) => Some(ContentPart::Text {
+            text: match &document.source {
+                DocumentSource::Text(text) => text.clone(),
+                _ => format!(
+                    "[document {} omitted: not supported by this provider]",
+                    document.name
+                ),
+            },
+        }),
+        ContentBlock::Video(_) => Some(ContentPart::Text {
+            text: "[video omitted: not supported by this provider]".to_string(),
+        }),
+        _ => None,
+    }
+}
+
+fn tool_result_text(items: &[ToolResultContent]) -> String {
+    items
+        .iter()
+        .map(|item| match item {
+            ToolResultContent::Text(TextBlock(text)) => text.clone(),
+            ToolResultContent::Json(json) => json.0.to_string(),
+            ToolResultContent::Image(_) => {
+                "[image omitted: not supported by this provider]".to_string()
+            }
+            ToolResultContent::Document(document) => format!(
+                "[document {} omitted: not supported by this provider]",
+                document.name
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    usage: Option<ChunkUsage>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct ChunkUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+}
+
+#[derive(Deserialize)]
+struct PromptTokensDetails {
+    #[serde(default)]
+    cached_tokens: u64,
+}
+
+impl From<ChunkUsage> for Usage {
+    fn from(usage: ChunkUsage) -> Self {
+        // Prompt tokens include those read from the cache.
+        let cache_read_tokens = usage.prompt_tokens_details.map_or(0, |d| d.cached_tokens);
+        Usage {
+            input_tokens: usage.prompt_tokens.saturating_sub(cache_read_tokens),
+            output_tokens: usage.completion_tokens,
+            cache_read_tokens,
+            cache_write_tokens: 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: Option<ChunkDelta>,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+    tool_calls: Option<Vec<ToolCallDelta>>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallDelta {
+    index: usize,
+    id: Option<String>,
+    function: Option<FunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct FunctionDelta {
+    name: Option<String>,
+    arguments: Option<String>,
+}
+
+/// A tool call that is still receiving argument deltas.
+struct PendingToolCall {
+    /// The server's index of the tool call within the choice.
+    call_index: usize,
+    /// The content block index assigned to the tool use.
+    index: usize,
+    id: String,
+    name: String,
+    arguments: String,
+}
+
+/// Turns chat completion chunks into stream events.
+///
+/// Chat completions have no explicit content block boundaries, so a text block ends when the first
+/// tool call starts, and tool calls end with the message since their argument deltas may
+/// interleave.
+#[derive(Default)]
+struct ChatStreamState {
+    started: bool,
+    next_index: usize,
+    text: Option<(usize, String)>,
+    tool_calls: Vec<PendingToolCall>,
+    content: Vec<ContentBlock>,
+    finish_reason: Option<String>,
+    usage: Option<Usage>,
+}
+
+impl ChatStreamState {
+    fn process(&mut self, chunk: ChatChunk) -> Vec<StreamEvent> {
+        let mut events = Vec::new();
+        if !self.started {
+            self.started = true;
+            events.push(StreamEvent::MessageStart {
+                role: Role::Assistant,
+            });
+        }
+
+        if let Some(usage) = chunk.usage {
+            self.usage = Some(usage.into());
+        }
+
+        for choice in chunk.choices {
+            if let Some(delta) = choice.delta {
+                if let Some(text) = delta.content.filter(|t| !t.is_empty()) {
+                    let index = match &mut self.text {
+                        Some((index, buffer)) => {
+                            buffer.push_str(&text);
+                            *index
+                        }
+                        None => {
+                            let index = self.next_index;
+                            self.next_index += 1;
+                            self.text = Some((index, text.clone()));
+                            events.push(StreamEvent::TextStart { index });
+                            index
+                        }
+                    };
+
+                    events.push(StreamEvent::TextDelta { index, delta: text });
+                }
+
+                for call in delta.tool_calls.unwrap_or_default() {
+                    self.process_tool_call(call, &mut events);
+                }
+            }
+
+            if let Some(reason) = choice.finish_reason {
+                self.finish_reason = Some(reason);
+            }
+        }
+
+        events
+    }
+
+    fn process_tool_call(&mut self, call: ToolCallDelta, events: &mut Vec<StreamEvent>) {
+        let (name, arguments) = call
+            .function
+            .map_or((None, None), |f| (f.name, f.arguments));
+
+        let position = self
+            .tool_calls
+            .iter()
+            .position(|pending| pending.call_index == call.index);
+
+        let pending = match position {
+            Some(position) => &mut self.tool_calls[position],
+            None => {
+                self.complete_text(events);
+
+                let index = self.next_index;
+                self.next_index += 1;
+                let id = call.id.unwrap_or_else(tool_use_id);
+                let name = name.clone().unwrap_or_default();
+
+                events.push(StreamEvent::ToolUseStart {
+                    index,
+                    id: id.clone(),
+                    name: name.clone(),
+                });
+
+                self.tool_calls.push(PendingToolCall {
+                    call_index: call.index,
+                    index,
+                    id,
+                    name,
+                    arguments: String::new(),
+                });
+                self.tool_calls.last_mut().unwrap()
+            }
+        };
+
+        if let Some(arguments) = arguments.filter(|a| !a.is_empty()) {
+            pending.arguments.push_str(&arguments);
+            events.push(StreamEvent::ToolInputDelta {
+                index: pending.index,
+                delta: arguments,
+            });
+        }
+    }
+
+    fn complete_text(&mut self, events: &mut Vec<StreamEvent>) {
+        if let Some((index, text)) = self.text.take() {
+            let block = ContentBlock::Text(TextBlock(text));
+            self.content.push(block.clone());
+            events.push(StreamEvent::ContentBlockComplete { index, block });
+        }
+    }
+
+    /// Completes the open content blocks and the message. Nothing is emitted if the server never
+    /// reported why generation finished, since the response was then cut short.
+    fn finish(mut self, latency: Duration) -> Vec<StreamEvent> {
+        let Some(finish_reason) = self.finish_reason.take() else {
+            return Vec::new();
+        };
+
+        let mut events = Vec::new();
+        self.complete_text(&mut events);
+
+        for call in std::mem::take(&mut self.tool_calls) {
+            let input = if call.arguments.trim().is_empty() {
+                serde_json::Value::Object(Default::default())
+            } else {
+                serde_json::from_str(&call.arguments).unwrap_or(serde_json::Value::Null)
+            };
+
+            let block = ContentBlock::ToolUse(ToolUseBlock {
+                id: call.id,
+                name: call.name,
+                input,
+            });
+            self.content.push(block.clone());
+            events.push(StreamEvent::ContentBlockComplete {
+                index: call.index,
+                block,
+            });
+        }
+
+        let has_tool_use = self
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse(_)));
+
+        // Some servers finish with "stop" even when the message contains tool calls.
+        let stop_reason = match finish_reason.as_str() {
+            "length" => StopReason::MaxTokens,
+            "content_filter" => StopReason::ContentFiltered,
+            "tool_calls" | "function_call" => StopReason::ToolUse,
+            _ if has_tool_use => StopReason::ToolUse,
+            _ => StopReason::EndTurn,
+        };
+
+        if let Some(usage) = self.usage {
+            events.push(StreamEvent::Metadata { usage, latency });
+        }
+
+        events.push(StreamEvent::MessageComplete {
+            message: Message {
+                role: Role::Assistant,
+                content: self.content,
+            },
+            stop_reason,
+        });
+
+        events
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Error, Result},
+    message::{Message, SystemPrompt},
+    state_provider::{StateProvider, file::write_atomically},
+};
+
+/// Everything needed to rebuild an agent after a restart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct Session {
+    /// Identifier used to load the session.
+    pub id: String,
+    /// When the session was first saved.
+    pub created_at: SystemTime,
+    /// When the session was last saved.
+    pub updated_at: SystemTime,
+    /// The agent's system prompt.
+    pub system_prompt: SystemPrompt,
+    /// The conversation so far.
+    pub messages: Vec<Message>,
+    /// The contents of the agent's state provider.
+    pub state: serde_json::Map<String, serde_json::Value>,
+    /// Application-defined metadata about the agent.
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Session {
+    pub fn new(id: impl Into<String>, system_prompt: SystemPrompt) -> Self {
+        let now = SystemTime::now();
+
+        Self {
+            id: id.into(),
+            created_at: now,
+            updated_at: now,
+            system_prompt,
+            messages: Vec::new(),
+            state: serde_json::Map::new(),
+            metadata: serde_json::Map::new(),
+        }
+    }
+
+    /// Refreshes the session from the agent's current conversation and state.
+    async fn capture(
+        &mut self,
+        messages: &std::sync::Mutex<Vec<Message>>,
+        state: &dyn StateProvider,
+    ) -> Result<()> {
+        self.state = state.snapshot().await?;
+        self.messages.clone_from(&messages.lock().unwrap());
+        self.updated_at = SystemTime::now();
+        Ok(())
+    }
+}
+
+/// Persists an agent's session after every message appended to its conversation.
+pub struct SessionArgs {
+    /// Identifier used to load the session.
+    pub id: String,
+    /// Where the session is stored.
+    pub manager: Box<dyn SessionManager>,
+    /// Application-defined metadata stored with the session.
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+impl std::fmt::Debug for SessionArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionArgs")
+            .field("id", &self.id)
+            .field("manager", &"SessionManager")
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+/// Stores agent sessions.
+#[async_trait::async_trait]
+pub trait SessionManager: Send + Sync {
+    /// Returns the session with the given id, if it exists.
+    async fn load(&self, id: &str) -> Result<Option<Session>>;
+
+    /// Creates or replaces a session.
+    async fn save(&self, session: &Session) -> Result<()>;
+
+    /// Removes a session. Removing a session that does not exist is not an error.
+    async fn delete(&self, id: &str) -> Result<()>;
+
+    fn boxed(self) -> Box<dyn SessionManager>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+}
+
+/// Keeps an agent's session up to date and saves it through its manager.
+pub(crate) struct SessionRecorder {
+    manager: Box<dyn SessionManager>,
+    session: tokio::sync::Mutex<Session>,
+}
+
+impl SessionRecorder {
+    pub(crate) fn new(manager: Box<dyn SessionManager>, session: Session) -> Self {
+        Self {
+            manager,
+            session: tokio::sync::Mutex::new(session),
+        }
+    }
+
+    /// Captures the agent's conversation and state and saves the session.
+    ///
+    /// The conversation is read while the session is locked, so concurrent calls never save an
+    /// older conversation over a newer one.
+    pub(crate) async fn record(
+        &self,
+        messages: &std::sync::Mutex<Vec<Message>>,
+        state: &dyn StateProvider,
+    ) -> Result<()> {
+        let mut session = self.session.lock().await;
+        session.capture(messages, state).await?;
+        self.manager.save(&session).await
+    }
+}
+
+/// A [`SessionManager`] that stores each session as a JSON file in a directory.
+#[derive(Clone, Debug)]
+pub struct FileSessionManager {
+    directory: PathBuf,
+}
+
+impl FileSessionManager {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    fn session_path(&self, id: &str) -> Result<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if !valid {
+            return Err(Error::InvalidSessionId(id.to_string()));
+        }
+
+        Ok(self.directory.join(format!("{id}.json")))
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionManager for FileSessionManager {
+    async fn load(&self, id: &str) -> Result<Option<Session>> {
+        match tokio::fs::read(self.session_path(id)?).await {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn save(&self, session: &Session) -> Result<()> {
+        let path = self.session_path(&session.id)?;
+        tokio::fs::create_dir_all(&self.directory).await?;
+        write_atomically(&path, &serde_json::to_vec_pretty(session)?).await
+    }
+
+    async fn delete(&self, id: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.session_path(id)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
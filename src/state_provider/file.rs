@@ -0,0 +1,190 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use tokio::io::AsyncWriteExt;
+
+use crate::{error::Result, state_provider::StateProvider};
+
+/// Values of every namespace in a state file, keyed by namespace and then by key.
+type Namespaces = HashMap<String, serde_json::Map<String, serde_json::Value>>;
+
+/// One lock per state file, keyed by canonical path, so that providers sharing a file within this
+/// process never interleave their read-modify-write cycles. An entry is removed once the last
+/// provider of its file is dropped.
+static FILE_LOCKS: LazyLock<Mutex<HashMap<PathBuf, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+/// A [`StateProvider`] that persists values to a JSON file.
+///
+/// Several providers may share one file by using different namespaces. Every write replaces the
+/// file atomically by writing a temporary file next to it and renaming it into place. The file is
+/// read again for every operation, so writes by other processes are always picked up.
+#[derive(Debug)]
+pub struct FileStateProvider {
+    path: PathBuf,
+    namespace: String,
+    canonical_path: PathBuf,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl FileStateProvider {
+    pub fn new(path: impl Into<PathBuf>, namespace: impl Into<String>) -> Self {
+        let path = path.into();
+        let canonical_path = canonical(&path);
+        let lock = Arc::clone(
+            FILE_LOCKS
+                .lock()
+                .unwrap()
+                .entry(canonical_path.clone())
+                .or_default(),
+        );
+
+        Self {
+            path,
+            namespace: namespace.into(),
+            canonical_path,
+            lock,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    async fn read(&self) -> Result<Namespaces> {
+        match tokio::fs::read(&self.path).await {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Namespaces::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn write(&self, namespaces: &Namespaces) -> Result<()> {
+        write_atomically(&self.path, &serde_json::to_vec_pretty(namespaces)?).await
+    }
+}
+
+impl Drop for FileStateProvider {
+    fn drop(&mut self) {
+        // New providers take their reference while holding the map's lock, so the count cannot
+        // grow while it is checked.
+        let mut locks = FILE_LOCKS.lock().unwrap();
+        if Arc::strong_count(&self.lock) == 2 {
+            locks.remove(&self.canonical_path);
+        }
+    }
+}
+
+/// Resolves `path` the same way for every spelling of it, whether or not the file exists yet.
+fn canonical(path: &Path) -> PathBuf {
+    if let Ok(path) = path.canonicalize() {
+        return path;
+    }
+
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|parent| parent.join(name))
+            .unwrap_or(path),
+        _ => path,
+    }
+}
+
+/// Replaces the file at `path` with `contents` by writing a temporary file next to it and renaming
+/// it into place, so readers never observe a partially written file.
+pub(crate) async fn write_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    // Concurrent writers, in this process or another, each use their own temporary file.
+    static WRITES: AtomicU64 = AtomicU64::new(0);
+
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let temp_path = path.with_file_name(file_name);
+
+    let result = async {
+        let mut file = tokio::fs::File::create(&temp_path).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        tokio::fs::rename(&temp_path, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+    }
+
+    Ok(result?)
+}
+
+#[async_trait::async_trait]
+impl StateProvider for FileStateProvider {
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let _guard = self.lock.lock().await;
+        let namespaces = self.read().await?;
+
+        Ok(namespaces
+            .get(&self.namespace)
+            .and_then(|values| values.get(key))
+            .cloned())
+    }
+
+    async fn set(&self, key: &str, value: serde_json::Value) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let mut namespaces = self.read().await?;
+
+        namespaces
+            .entry(self.namespace.clone())
+            .or_default()
+            .insert(key.to_string(), value);
+
+        self.write(&namespaces).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let _guard = self.lock.lock().await;
+        let mut namespaces = self.read().await?;
+
+        let removed = namespaces
+            .get_mut(&self.namespace)
+            .and_then(|values| values.remove(key));
+
+        if removed.is_some() {
+            self.write(&namespaces).await?;
+        }
+
+        Ok(removed)
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        let _guard = self.lock.lock().await;
+        let namespaces = self.read().await?;
+
+        Ok(namespaces
+            .get(&self.namespace)
+            .map(|values| values.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn snapshot(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let _guard = self.lock.lock().await;
+        let mut namespaces = self.read().await?;
+
+        Ok(namespaces.remove(&self.namespace).unwrap_or_default())
+    }
+}
@@ -0,0 +1,111 @@
+pub mod file;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::error::Result;
+
+/// Key-value state shared by an agent and its tools.
+///
+/// Values are stored as JSON so any provider can hold any serializable type. Use
+/// [`get_as`](dyn StateProvider::get_as) and [`set_as`](dyn StateProvider::set_as) to work with
+/// typed values.
+#[async_trait::async_trait]
+pub trait StateProvider: Send + Sync + std::fmt::Debug {
+    /// Returns the value stored under `key`, if any.
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>>;
+
+    /// Stores `value` under `key`, replacing any previous value.
+    async fn set(&self, key: &str, value: serde_json::Value) -> Result<()>;
+
+    /// Removes the value stored under `key`, returning it if it existed.
+    async fn delete(&self, key: &str) -> Result<Option<serde_json::Value>>;
+
+    /// Returns every key currently stored.
+    async fn list(&self) -> Result<Vec<String>>;
+
+    /// Returns every key with its value.
+    ///
+    /// The default implementation reads each key returned by [`list`](StateProvider::list) in
+    /// turn. Providers that can read all of their values at once should override it.
+    async fn snapshot(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let mut values = serde_json::Map::new();
+        for key in self.list().await? {
+            if let Some(value) = self.get(&key).await? {
+                values.insert(key, value);
+            }
+        }
+
+        Ok(values)
+    }
+
+    fn boxed(self) -> Box<dyn StateProvider>
+    where
+        Self: Sized + 'static,
+    {
+        Box::new(self)
+    }
+}
+
+impl dyn StateProvider + '_ {
+    /// Returns the value stored under `key`, deserialized into `T`.
+    pub async fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
+        match self.get(key).await? {
+            Some(value) => Ok(Some(serde_json::from_value(value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Serializes `value` and stores it under `key`.
+    pub async fn set_as<T: Serialize + ?Sized>(&self, key: &str, value: &T) -> Result<()> {
+        self.set(key, serde_json::to_value(value)?).await
+    }
+}
+
+/// A [`StateProvider`] that keeps values in memory for the lifetime of the agent.
+#[derive(Debug, Default)]
+pub struct MemoryStateProvider {
+    values: Mutex<HashMap<String, serde_json::Value>>,
+}
+
+impl MemoryStateProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl From<HashMap<String, serde_json::Value>> for MemoryStateProvider {
+    fn from(values: HashMap<String, serde_json::Value>) -> Self {
+        Self {
+            values: Mutex::new(values),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl StateProvider for MemoryStateProvider {
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        Ok(self.values.lock().unwrap().get(key).cloned())
+    }
+
+    async fn set(&self, key: &str, value: serde_json::Value) -> Result<()> {
+        self.values.lock().unwrap().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        Ok(self.values.lock().unwrap().remove(key))
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        Ok(self.values.lock().unwrap().keys().cloned().collect())
+    }
+
+    async fn snapshot(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let values = self.values.lock().unwrap();
+        Ok(values.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+    }
+}
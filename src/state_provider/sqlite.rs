@@ -0,0 +1,153 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::{error::Result, state_provider::StateProvider};
+
+/// A [`StateProvider`] backed by a SQLite database.
+///
+/// Values are stored as JSON text in a `strands_state` table keyed by namespace and key, so
+/// several agents can share one database without key collisions.
+#[derive(Debug, Clone)]
+pub struct SqliteStateProvider {
+    connection: Arc<Mutex<Connection>>,
+    namespace: String,
+}
+
+impl SqliteStateProvider {
+    /// Opens or creates the database at `path`.
+    pub fn open(path: impl AsRef<Path>, namespace: impl Into<String>) -> Result<Self> {
+        Self::from_connection(Connection::open(path)?, namespace)
+    }
+
+    /// Opens a database that lives only as long as this provider and its clones.
+    pub fn open_in_memory(namespace: impl Into<String>) -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?, namespace)
+    }
+
+    pub fn from_connection(connection: Connection, namespace: impl Into<String>) -> Result<Self> {
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS strands_state (
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (namespace, key)
+            )",
+        )?;
+
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            namespace: namespace.into(),
+        })
+    }
+
+    /// Returns a provider over the same database using a different namespace.
+    pub fn with_namespace(&self, namespace: impl Into<String>) -> Self {
+        Self {
+            connection: Arc::clone(&self.connection),
+            namespace: namespace.into(),
+        }
+    }
+
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Runs a query on a blocking thread so SQLite I/O never stalls the async runtime.
+    async fn run<T, F>(&self, query: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection, &str) -> Result<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        let namespace = self.namespace.clone();
+
+        tokio::task::spawn_blocking(move || query(&connection.lock().unwrap(), &namespace))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+}
+
+#[async_trait::async_trait]
+impl StateProvider for SqliteStateProvider {
+    async fn get(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let key = key.to_string();
+        self.run(move |connection, namespace| {
+            let value: Option<String> = connection
+                .query_row(
+                    "SELECT value FROM strands_state WHERE namespace = ?1 AND key = ?2",
+                    params![namespace, key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+        })
+        .await
+    }
+
+    async fn set(&self, key: &str, value: serde_json::Value) -> Result<()> {
+        let key = key.to_string();
+        let value = serde_json::to_string(&value)?;
+        self.run(move |connection, namespace| {
+            connection.execute(
+                "INSERT INTO strands_state (namespace, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (namespace, key) DO UPDATE SET value = excluded.value",
+                params![namespace, key, value],
+            )?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let key = key.to_string();
+        self.run(move |connection, namespace| {
+            let value: Option<String> = connection
+                .query_row(
+                    "DELETE FROM strands_state WHERE namespace = ?1 AND key = ?2 RETURNING value",
+                    params![namespace, key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+
+            Ok(value.map(|v| serde_json::from_str(&v)).transpose()?)
+        })
+        .await
+    }
+
+    async fn list(&self) -> Result<Vec<String>> {
+        self.run(move |connection, namespace| {
+            let mut statement = connection
+                .prepare("SELECT key FROM strands_state WHERE namespace = ?1 ORDER BY key")?;
+            let keys = statement
+                .query_map(params![namespace], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+
+            Ok(keys)
+        })
+        .await
+    }
+
+    async fn snapshot(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        self.run(move |connection, namespace| {
+            let mut statement =
+                connection.prepare("SELECT key, value FROM strands_state WHERE namespace = ?1")?;
+            let rows = statement
+                .query_map(params![namespace], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<rusqlite::Result<Vec<(String, String)>>>()?;
+
+            let mut values = serde_json::Map::new();
+            for (key, value) in rows {
+                values.insert(key, serde_json::from_str(&value)?);
+            }
+
+            Ok(values)
+        })
+        .await
+    }
+}
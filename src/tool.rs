@@ -1,4 +1,11 @@
-use crate::message::ToolResult;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use crate::{
+    message::{ToolResult, ToolUseBlock},
+    state_provider::StateProvider,
+};
 
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
@@ -20,10 +27,18 @@ impl From<rmcp::model::Tool> for ToolSpec {
     }
 }
 
-pub struct ToolContext;
+/// Context supplied to a tool when it is invoked by an agent.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct ToolContext {
+    /// The tool use block that triggered this invocation.
+    pub tool_use: ToolUseBlock,
+    /// State shared by the agent and all of its tools.
+    pub state: Arc<dyn StateProvider>,
+}
 
 #[async_trait::async_trait]
-pub trait Tool<E> {
+pub trait Tool<E>: Send + Sync {
     fn spec(&self) -> ToolSpec;
 
     async fn invoke(
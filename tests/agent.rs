@@ -0,0 +1,390 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::model::{
+    ScriptedModelProvider, events, reply, response, run_turn, text, tool_calls, tool_use, usage,
+};
+use futures::StreamExt;
+use strands::{
+    Error,
+    agent::{Agent, AgentArgs, AgentEvent, AgentMetrics, InferenceConfig, TurnOptions},
+    message::{ContentBlock, Message, Role, StopReason, TextBlock, ToolResult, ToolResultContent},
+    model::model_provider::{ModelPricing, ToolPolicy, Usage},
+    tool::{Tool, ToolContext, ToolSpec},
+};
+
+struct WeatherTool;
+
+#[async_trait::async_trait]
+impl Tool<()> for WeatherTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "weather".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        _input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        Ok(Ok(vec![ToolResultContent::Text(TextBlock("Sunny".into()))]))
+    }
+}
+
+#[tokio::test]
+async fn emits_cycle_and_tool_events_in_order() {
+    let provider = ScriptedModelProvider::new([
+        tool_calls([tool_use("call_1", "weather", serde_json::json!({}))]),
+        reply("Sunny."),
+    ]);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Weather in Oslo?")],
+            tools: vec![WeatherTool.boxed()],
+            ..Default::default()
+        },
+    );
+
+    let events = run_turn(agent.turn()).await;
+    let events: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            AgentEvent::Model(_) => None,
+            AgentEvent::CycleStarted => Some("CycleStarted".to_string()),
+            AgentEvent::ToolCallStarted { tool_use } => {
+                Some(format!("ToolCallStarted {}", tool_use.id))
+            }
+            AgentEvent::ToolCallCompleted { result, .. } => {
+                Some(format!("ToolCallCompleted {}", result.id))
+            }
+            AgentEvent::CycleCompleted => Some("CycleCompleted".to_string()),
+            AgentEvent::TurnCompleted { stop_reason } => {
+                Some(format!("TurnCompleted {stop_reason:?}"))
+            }
+            other => panic!("unexpected event: {other:?}"),
+        })
+        .collect();
+
+    assert_eq!(
+        events,
+        [
+            "CycleStarted",
+            "ToolCallStarted call_1",
+            "ToolCallCompleted call_1",
+            "CycleCompleted",
+            "CycleStarted",
+            "CycleCompleted",
+            "TurnCompleted EndTurn",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn accumulates_metrics_per_turn_and_in_total() {
+    let provider = ScriptedModelProvider::new([
+        response(
+            vec![tool_use("call_1", "weather", serde_json::json!({}))],
+            StopReason::ToolUse,
+            usage(100, 20, 0),
+        ),
+        response(vec![text("Sunny.")], StopReason::EndTurn, usage(10, 5, 120)),
+        response(
+            vec![text("Still sunny.")],
+            StopReason::EndTurn,
+            usage(30, 6, 0),
+        ),
+    ]);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Weather in Oslo?")],
+            tools: vec![WeatherTool.boxed()],
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let first_turn = AgentMetrics {
+        usage: usage(110, 25, 120),
+        model_calls: 2,
+        tool_calls: 1,
+        model_latency: Duration::from_millis(20),
+        ..Default::default()
+    };
+    assert_eq!(agent.turn_metrics(), first_turn);
+    assert_eq!(agent.metrics(), first_turn);
+
+    run_turn(agent.turn()).await;
+
+    assert_eq!(
+        agent.turn_metrics(),
+        AgentMetrics {
+            usage: usage(30, 6, 0),
+            model_calls: 1,
+            tool_calls: 0,
+            model_latency: Duration::from_millis(10),
+            ..Default::default()
+        }
+    );
+    assert_eq!(
+        agent.metrics(),
+        AgentMetrics {
+            usage: usage(140, 31, 120),
+            model_calls: 3,
+            tool_calls: 1,
+            model_latency: Duration::from_millis(30),
+            ..Default::default()
+        }
+    );
+}
+
+#[tokio::test]
+async fn stops_turns_once_the_spend_limit_is_reached() {
+    let provider = ScriptedModelProvider::new([response(
+        vec![text("Hello.")],
+        StopReason::EndTurn,
+        usage(1_000_000, 500_000, 0),
+    )]);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            pricing: Some(ModelPricing {
+                input: 1.0,
+                output: 2.0,
+                ..Default::default()
+            }),
+            spend_limit: Some(2.0),
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+    assert!(
+        events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::ModelCallCost { cost } if *cost == 2.0))
+    );
+    assert_eq!(agent.metrics().cost, 2.0);
+
+    let error = agent
+        .turn()
+        .next()
+        .await
+        .unwrap()
+        .expect_err("the spend limit was reached");
+    assert_eq!(
+        error.to_string(),
+        "Spend limit of $2.00 exceeded with $2.00 spent"
+    );
+}
+
+#[tokio::test]
+async fn stops_before_running_tools_once_the_spend_limit_is_reached() {
+    let provider = ScriptedModelProvider::new([response(
+        vec![tool_use("call_1", "weather", serde_json::json!({}))],
+        StopReason::ToolUse,
+        usage(1_000_000, 0, 0),
+    )]);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Weather in Oslo?")],
+            tools: vec![WeatherTool.boxed()],
+            pricing: Some(ModelPricing {
+                input: 1.0,
+                ..Default::default()
+            }),
+            spend_limit: Some(1.0),
+            ..Default::default()
+        },
+    );
+
+    let results: Vec<_> = agent.turn().collect().await;
+    let Some(Err(error)) = results.last() else {
+        panic!("the turn did not fail");
+    };
+    assert!(matches!(
+        error.downcast_ref::<Error>(),
+        Some(Error::SpendLimitExceeded { .. })
+    ));
+    assert!(
+        !results
+            .iter()
+            .any(|event| matches!(event, Ok(AgentEvent::ToolCallStarted { .. })))
+    );
+
+    // The tool use is still answered, without the tool having run.
+    let messages = agent.messages();
+    assert_eq!(messages.len(), 3);
+    assert!(matches!(
+        &messages[2].content[0],
+        ContentBlock::ToolResult(result) if result.content.is_err()
+    ));
+}
+
+#[tokio::test]
+async fn rejects_a_spend_limit_without_pricing() {
+    let provider = ScriptedModelProvider::new([reply("Hello.")]);
+    let calls = Arc::clone(&provider.calls);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            spend_limit: Some(1.0),
+            ..Default::default()
+        },
+    );
+
+    let error = agent
+        .turn()
+        .next()
+        .await
+        .unwrap()
+        .expect_err("the spend limit cannot be enforced");
+    assert!(matches!(
+        error.downcast_ref::<Error>(),
+        Some(Error::SpendLimitWithoutPricing)
+    ));
+    assert!(calls.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn overrides_the_tool_policy_per_turn() {
+    let provider = ScriptedModelProvider::new([reply("Hello."), reply("Hello again.")]);
+    let calls = Arc::clone(&provider.calls);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            tools: vec![Box::new(WeatherTool)],
+            tool_policy: Some(ToolPolicy::None),
+            parallel_tool_use: Some(false),
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+    run_turn(agent.turn_with(TurnOptions {
+        tool_policy: Some(ToolPolicy::Required),
+        ..Default::default()
+    }))
+    .await;
+
+    let args: Vec<_> = calls
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|call| call.args.clone())
+        .collect();
+    assert!(matches!(args[0].tool_policy, Some(ToolPolicy::None)));
+    assert!(matches!(args[1].tool_policy, Some(ToolPolicy::Required)));
+    assert!(
+        args.iter()
+            .all(|args| args.parallel_tool_use == Some(false))
+    );
+}
+
+#[tokio::test]
+async fn overrides_inference_parameters_per_turn() {
+    let provider = ScriptedModelProvider::new([reply("Hello."), reply("Hello again.")]);
+    let calls = Arc::clone(&provider.calls);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            inference: InferenceConfig {
+                max_tokens: Some(1024),
+                temperature: Some(0.2),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+    run_turn(agent.turn_with(TurnOptions {
+        inference: Some(InferenceConfig {
+            max_tokens: Some(256),
+            stop_sequences: Some(vec!["END".into()]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }))
+    .await;
+
+    let args: Vec<_> = calls
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|call| call.args.clone())
+        .collect();
+    assert_eq!(args[0].max_tokens, Some(1024));
+    assert_eq!(args[0].temperature, Some(0.2));
+    assert_eq!(args[0].stop_sequences, None);
+    assert_eq!(args[1].max_tokens, Some(256));
+    assert_eq!(args[1].temperature, Some(0.2));
+    assert_eq!(args[1].stop_sequences, Some(vec!["END".into()]));
+}
+
+#[tokio::test]
+async fn continues_responses_cut_off_by_max_tokens() {
+    let provider = ScriptedModelProvider::new([
+        response(
+            vec![text("Once upon")],
+            StopReason::MaxTokens,
+            Usage::default(),
+        ),
+        response(
+            vec![text(" a time")],
+            StopReason::MaxTokens,
+            Usage::default(),
+        ),
+    ]);
+
+    let calls = Arc::clone(&provider.calls);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Tell me a story")],
+            max_tokens_continuations: 1,
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::TurnCompleted {
+            stop_reason: StopReason::MaxTokens
+        })
+    ));
+    assert_eq!(agent.turn_metrics().model_calls, 2);
+
+    // The prompt to continue is only part of the continuation call.
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls[1].messages.len(), 3);
+    assert!(matches!(calls[1].messages[2].role, Role::User));
+
+    // The continuation completes the truncated message.
+    let messages = agent.messages();
+    assert_eq!(messages.len(), 2);
+    assert!(matches!(messages[1].role, Role::Assistant));
+    assert_eq!(messages[1].content.len(), 1);
+    assert!(
+        matches!(&messages[1].content[0], ContentBlock::Text(text) if text.0 == "Once upon a time")
+    );
+}
@@ -0,0 +1,309 @@
+mod common;
+
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    message::{
+        CachePointBlock, ContentBlock, GuardBlock, GuardQualifier, GuardText, Message, Role,
+        StopReason, SystemPrompt, SystemPromptBlock, TextBlock, ToolResultBlock, ToolResultContent,
+        ToolUseBlock, VideoBlock, VideoFormat, VideoSource,
+    },
+    model::{
+        bedrock::{BedrockCredentials, BedrockModelProvider},
+        model_provider::{ModelProvider, StreamArgs, StreamEvent, ToolPolicy, Usage},
+    },
+    tool::ToolSpec,
+};
+
+/// Encodes an event stream message with string headers.
+fn message(headers: &[(&str, &str)], payload: serde_json::Value) -> Vec<u8> {
+    let mut encoded_headers = Vec::new();
+    for (name, value) in headers {
+        encoded_headers.push(name.len() as u8);
+        encoded_headers.extend_from_slice(name.as_bytes());
+        encoded_headers.push(7);
+        encoded_headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        encoded_headers.extend_from_slice(value.as_bytes());
+    }
+
+    let payload = serde_json::to_vec(&payload).unwrap();
+    let total_len = 12 + encoded_headers.len() + payload.len() + 4;
+
+    let mut message = Vec::new();
+    message.extend_from_slice(&(total_len as u32).to_be_bytes());
+    message.extend_from_slice(&(encoded_headers.len() as u32).to_be_bytes());
+    message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+    message.extend_from_slice(&encoded_headers);
+    message.extend_from_slice(&payload);
+    message.extend_from_slice(&crc32fast::hash(&message).to_be_bytes());
+    message
+}
+
+fn event(event_type: &str, payload: serde_json::Value) -> Vec<u8> {
+    message(
+        &[(":message-type", "event"), (":event-type", event_type)],
+        payload,
+    )
+}
+
+#[tokio::test]
+async fn streams_converse_events() {
+    let body = [
+        event("messageStart", json!({ "role": "assistant" })),
+        event(
+            "contentBlockDelta",
+            json!({ "contentBlockIndex": 0, "delta": { "reasoningContent": { "text": "Hmm." } } }),
+        ),
+        event(
+            "contentBlockDelta",
+            json!({ "contentBlockIndex": 0, "delta": { "reasoningContent": { "signature": "sig" } } }),
+        ),
+        event("contentBlockStop", json!({ "contentBlockIndex": 0 })),
+        event(
+            "contentBlockDelta",
+            json!({ "contentBlockIndex": 1, "delta": { "text": "Checking." } }),
+        ),
+        event("contentBlockStop", json!({ "contentBlockIndex": 1 })),
+        event(
+            "contentBlockStart",
+            json!({
+                "contentBlockIndex": 2,
+                "start": { "toolUse": { "toolUseId": "tooluse_1", "name": "weather" } },
+            }),
+        ),
+        event(
+            "contentBlockDelta",
+            json!({ "contentBlockIndex": 2, "delta": { "toolUse": { "input": "{\"city\":" } } }),
+        ),
+        event(
+            "contentBlockDelta",
+            json!({ "contentBlockIndex": 2, "delta": { "toolUse": { "input": "\"Oslo\"}" } } }),
+        ),
+        event("contentBlockStop", json!({ "contentBlockIndex": 2 })),
+        event("messageStop", json!({ "stopReason": "tool_use" })),
+        event(
+            "metadata",
+            json!({
+                "usage": {
+                    "inputTokens": 30,
+                    "outputTokens": 14,
+                    "totalTokens": 144,
+                    "cacheReadInputTokens": 100,
+                    "cacheWriteInputTokens": 0,
+                },
+                "metrics": { "latencyMs": 420 },
+            }),
+        ),
+    ]
+    .concat();
+
+    let (base_url, request) = common::serve_once("application/vnd.amazon.eventstream", body).await;
+
+    let credentials = BedrockCredentials::new("AKIDEXAMPLE", "secret").with_session_token("token");
+    let provider = BedrockModelProvider::new(
+        "us-east-1",
+        "anthropic.claude-3-5-sonnet-20240620-v1:0",
+        credentials,
+    )
+    .with_endpoint(base_url);
+
+    let mut args = StreamArgs::default();
+    args.system_prompt = Some(SystemPrompt::Structured(vec![
+        SystemPromptBlock::Text(TextBlock("Be brief.".into())),
+        SystemPromptBlock::CachePoint(CachePointBlock::Default),
+    ]));
+    args.tool_specs = Some(vec![ToolSpec {
+        name: "weather".into(),
+        input_schema: json!({ "type": "object" }).as_object().unwrap().clone(),
+        ..Default::default()
+    }]);
+    args.tool_policy = Some(ToolPolicy::Specific {
+        name: "weather".into(),
+    });
+    args.tool_cache_point = Some(CachePointBlock::Default);
+    args.max_tokens = Some(512);
+
+    let messages = [Message {
+        role: Role::User,
+        content: vec![
+            ContentBlock::Guard(GuardBlock::Text(GuardText {
+                qualifiers: vec![GuardQualifier::Query],
+                text: "Weather in Oslo?".into(),
+            })),
+            ContentBlock::Video(VideoBlock {
+                format: VideoFormat::Tgp,
+                source: VideoSource::Bytes(b"video".to_vec()),
+            }),
+        ],
+    }];
+
+    let events: Vec<StreamEvent> = provider
+        .stream(&messages, &args)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert!(matches!(events[0], StreamEvent::MessageStart { .. }));
+    assert!(matches!(
+        events[1],
+        StreamEvent::ReasoningStart { index: 0 }
+    ));
+
+    assert!(matches!(
+        &events[events.len() - 2],
+        StreamEvent::Metadata { usage, .. } if *usage == Usage {
+            input_tokens: 30,
+            output_tokens: 14,
+            cache_read_tokens: 100,
+            cache_write_tokens: 0,
+        }
+    ));
+
+    let Some(StreamEvent::MessageComplete {
+        message,
+        stop_reason,
+    }) = events.last()
+    else {
+        panic!("stream did not complete the message");
+    };
+
+    assert!(matches!(stop_reason, StopReason::ToolUse));
+    assert!(matches!(
+        &message.content[0],
+        ContentBlock::Reasoning(reasoning) if reasoning.text == "Hmm." && reasoning.signature == "sig"
+    ));
+    assert!(matches!(&message.content[1], ContentBlock::Text(text) if text.0 == "Checking."));
+    assert!(matches!(
+        &message.content[2],
+        ContentBlock::ToolUse(tool_use) if tool_use.input == json!({ "city": "Oslo" })
+    ));
+
+    let request = request.await.unwrap();
+    assert!(request.head.starts_with(
+        "post /model/anthropic.claude-3-5-sonnet-20240620-v1%3a0/converse-stream http/1.1"
+    ));
+    assert!(
+        request
+            .head
+            .contains("authorization: aws4-hmac-sha256 credential=akidexample/")
+    );
+    assert!(request.head.contains("/us-east-1/bedrock/aws4_request"));
+    assert!(request.head.contains("x-amz-security-token: token"));
+
+    assert_eq!(
+        request.body,
+        json!({
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "guardContent": { "text": { "text": "Weather in Oslo?", "qualifiers": ["query"] } } },
+                    { "video": { "format": "three_gp", "source": { "bytes": "dmlkZW8=" } } },
+                ],
+            }],
+            "system": [{ "text": "Be brief." }, { "cachePoint": { "type": "default" } }],
+            "inferenceConfig": { "maxTokens": 512 },
+            "toolConfig": {
+                "tools": [
+                    {
+                        "toolSpec": { "name": "weather", "inputSchema": { "json": { "type": "object" } } },
+                    },
+                    { "cachePoint": { "type": "default" } },
+                ],
+                "toolChoice": { "tool": { "name": "weather" } },
+            },
+        })
+    );
+}
+
+#[tokio::test]
+async fn reports_exceptions() {
+    let body = message(
+        &[
+            (":message-type", "exception"),
+            (":exception-type", "throttlingException"),
+        ],
+        json!({ "message": "Too many requests" }),
+    );
+
+    let (base_url, _request) = common::serve_once("application/vnd.amazon.eventstream", body).await;
+
+    let provider = BedrockModelProvider::new(
+        "us-east-1",
+        "amazon.nova-lite-v1:0",
+        BedrockCredentials::new("AKIDEXAMPLE", "secret"),
+    )
+    .with_endpoint(base_url);
+
+    let events: Vec<_> = provider
+        .stream(&[Message::new_user("Hi")], &StreamArgs::default())
+        .collect()
+        .await;
+    let error = events.into_iter().find_map(Result::err).unwrap();
+
+    assert_eq!(
+        error.to_string(),
+        "Bedrock reported throttlingException: Too many requests"
+    );
+}
+
+#[tokio::test]
+async fn sends_tool_history_as_text_when_tools_are_disabled() {
+    let body = [
+        event("messageStart", json!({ "role": "assistant" })),
+        event("messageStop", json!({ "stopReason": "end_turn" })),
+    ]
+    .concat();
+
+    let (base_url, request) = common::serve_once("application/vnd.amazon.eventstream", body).await;
+
+    let provider = BedrockModelProvider::new(
+        "us-east-1",
+        "amazon.nova-lite-v1:0",
+        BedrockCredentials::new("AKIDEXAMPLE", "secret"),
+    )
+    .with_endpoint(base_url);
+
+    let mut args = StreamArgs::default();
+    args.tool_specs = Some(vec![ToolSpec {
+        name: "weather".into(),
+        ..Default::default()
+    }]);
+    args.tool_policy = Some(ToolPolicy::None);
+
+    let messages = [
+        Message::new_user("Weather in Oslo?"),
+        Message {
+            role: Role::Assistant,
+            content: vec![ContentBlock::ToolUse(ToolUseBlock {
+                id: "call_1".into(),
+                name: "weather".into(),
+                input: json!({ "city": "Oslo" }),
+            })],
+        },
+        Message {
+            role: Role::User,
+            content: vec![ContentBlock::ToolResult(ToolResultBlock {
+                id: "call_1".into(),
+                content: Ok(vec![ToolResultContent::Text(TextBlock("Sunny".into()))]),
+            })],
+        },
+    ];
+
+    let _: Vec<_> = provider.stream(&messages, &args).collect().await;
+
+    let request = request.await.unwrap();
+    assert_eq!(
+        request.body,
+        json!({
+            "messages": [
+                { "role": "user", "content": [{ "text": "Weather in Oslo?" }] },
+                {
+                    "role": "assistant",
+                    "content": [{ "text": r#"[tool call call_1: weather with input {"city":"Oslo"}]"# }],
+                },
+                { "role": "user", "content": [{ "text": "[tool result for call_1: Sunny]" }] },
+            ],
+            "inferenceConfig": {},
+        })
+    );
+}
@@ -0,0 +1,210 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::model::{
+    ScriptedModelProvider, assert_tool_uses_answered, events, reply, response, run_turn,
+    tool_calls, tool_use,
+};
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    agent::{Agent, AgentArgs, AgentEvent, AgentStream, CancellationToken},
+    hook::Hook,
+    message::{
+        ContentBlock, Message, StopReason, TextBlock, ToolResult, ToolResultBlock,
+        ToolResultContent,
+    },
+    model::model_provider::Usage,
+    tool::{Tool, ToolContext, ToolSpec},
+};
+
+/// Never finishes.
+struct HangingTool;
+
+#[async_trait::async_trait]
+impl Tool<()> for HangingTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "hang".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        _input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        futures::future::pending().await
+    }
+}
+
+/// Never returns once a message with tool uses is appended.
+struct HangingHook;
+
+#[async_trait::async_trait]
+impl Hook for HangingHook {
+    async fn message_appended(&self, message: &Message) {
+        if message
+            .content
+            .iter()
+            .any(|block| matches!(block, ContentBlock::ToolUse(_)))
+        {
+            futures::future::pending::<()>().await;
+        }
+    }
+}
+
+fn hanging_agent(provider: ScriptedModelProvider, hooks: Vec<Box<dyn Hook>>) -> Agent<()> {
+    Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Wait")],
+            tools: vec![HangingTool.boxed()],
+            hooks,
+            ..Default::default()
+        },
+    )
+}
+
+/// Polls the turn until it stalls, then drops it.
+async fn drop_when_stalled(mut stream: AgentStream) -> Vec<AgentEvent> {
+    let mut events = Vec::new();
+    while let Ok(Some(event)) = tokio::time::timeout(Duration::from_millis(50), stream.next()).await
+    {
+        events.push(event.unwrap());
+    }
+
+    events
+}
+
+fn tool_results(messages: &[Message]) -> Vec<ToolResultBlock> {
+    messages
+        .iter()
+        .flat_map(|message| &message.content)
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult(result) => Some(result.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn result_text(result: &ToolResultBlock) -> &str {
+    let content = result.content.as_ref().unwrap_or_else(|e| e);
+    match &content[0] {
+        ToolResultContent::Text(TextBlock(text)) => text,
+        other => panic!("unexpected tool result content: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn answers_running_tool_calls_when_cancelled() {
+    let provider = ScriptedModelProvider::new([tool_calls([
+        tool_use("call_1", "hang", json!({})),
+        tool_use("call_2", "hang", json!({})),
+    ])]);
+    let mut agent = hanging_agent(provider, Vec::new());
+
+    let cancellation = CancellationToken::new();
+    let mut stream = agent.turn_with_cancellation(cancellation.clone());
+    let mut events = Vec::new();
+    while let Some(event) = stream.next().await {
+        let event = event.unwrap();
+        if matches!(event, AgentEvent::ToolCallStarted { .. }) {
+            cancellation.cancel();
+        }
+        events.push(event);
+    }
+    drop(stream);
+
+    assert!(matches!(events.last(), Some(AgentEvent::Cancelled)));
+
+    let messages = agent.messages();
+    assert_tool_uses_answered(&messages);
+    let results = tool_results(&messages);
+    assert_eq!(results.len(), 2);
+    for result in &results {
+        assert!(result.content.is_err());
+        assert!(result_text(result).ends_with("turn was cancelled"));
+    }
+}
+
+#[tokio::test]
+async fn answers_running_tool_calls_when_dropped() {
+    let provider =
+        ScriptedModelProvider::new([tool_calls([tool_use("call_1", "hang", json!({}))])]);
+    let mut agent = hanging_agent(provider, Vec::new());
+
+    let events = drop_when_stalled(agent.turn()).await;
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::ToolCallStarted { .. })
+    ));
+
+    let messages = agent.messages();
+    assert_eq!(messages.len(), 3);
+    assert_tool_uses_answered(&messages);
+}
+
+#[tokio::test]
+async fn answers_tool_calls_when_dropped_while_their_message_is_appended() {
+    let provider =
+        ScriptedModelProvider::new([tool_calls([tool_use("call_1", "hang", json!({}))])]);
+    let mut agent = hanging_agent(provider, vec![HangingHook.boxed()]);
+
+    let events = drop_when_stalled(agent.turn()).await;
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, AgentEvent::ToolCallStarted { .. }))
+    );
+
+    let messages = agent.messages();
+    assert_eq!(messages.len(), 3);
+    assert_tool_uses_answered(&messages);
+}
+
+#[tokio::test]
+async fn answers_tool_calls_of_truncated_responses() {
+    let provider = ScriptedModelProvider::new([response(
+        vec![tool_use("call_1", "hang", json!({}))],
+        StopReason::MaxTokens,
+        Usage::default(),
+    )]);
+    let mut agent = hanging_agent(provider, Vec::new());
+
+    let events = run_turn(agent.turn()).await;
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::TurnCompleted {
+            stop_reason: StopReason::MaxTokens
+        })
+    ));
+
+    let messages = agent.messages();
+    assert_tool_uses_answered(&messages);
+    let results = tool_results(&messages);
+    assert!(results[0].content.is_err());
+    assert_eq!(
+        result_text(&results[0]),
+        "Tool hang was not executed: the response ended before its tool calls could run"
+    );
+}
+
+#[tokio::test]
+async fn keeps_the_conversation_usable_after_a_cancelled_turn() {
+    let provider = ScriptedModelProvider::new([
+        tool_calls([tool_use("call_1", "hang", json!({}))]),
+        reply("Sorry, that took too long."),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = hanging_agent(provider, Vec::new());
+
+    drop_when_stalled(agent.turn()).await;
+    events(agent.turn()).await;
+
+    // The next model call sees every tool use answered.
+    assert_tool_uses_answered(&calls.lock().unwrap()[1].messages);
+    assert_eq!(agent.messages().len(), 4);
+}
@@ -0,0 +1,67 @@
+//! Helpers shared by the integration tests. Each test crate uses its own subset of them.
+#![allow(dead_code)]
+
+pub mod model;
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+/// A request received by [`serve_once`].
+pub struct Request {
+    /// The request line and headers, lowercased.
+    pub head: String,
+    pub body: serde_json::Value,
+}
+
+/// Serves a single HTTP request with `body` and returns the server's base URL along with a handle
+/// that resolves to the request it received.
+pub async fn serve_once(
+    content_type: &'static str,
+    body: impl AsRef<[u8]> + Send + 'static,
+) -> (String, tokio::task::JoinHandle<Request>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        let mut request = Vec::new();
+        let mut buffer = [0; 4096];
+        let body_start = loop {
+            let read = socket.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            if let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break end + 4;
+            }
+        };
+
+        let head = String::from_utf8_lossy(&request[..body_start]).to_lowercase();
+        let content_length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+
+        while request.len() < body_start + content_length {
+            let read = socket.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+        }
+
+        let response =
+            format!("HTTP/1.1 200 OK\r\ncontent-type: {content_type}\r\nconnection: close\r\n\r\n");
+        socket.write_all(response.as_bytes()).await.unwrap();
+        socket.write_all(body.as_ref()).await.unwrap();
+        socket.shutdown().await.unwrap();
+
+        Request {
+            head,
+            body: serde_json::from_slice(&request[body_start..]).unwrap(),
+        }
+    });
+
+    (base_url, handle)
+}
@@ -0,0 +1,201 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::StreamExt;
+use strands::{
+    agent::{AgentEvent, AgentStream},
+    message::{ContentBlock, Message, Role, StopReason, TextBlock, ToolUseBlock},
+    model::model_provider::{
+        ModelProvider, ModelProviderError, ModelProviderStream, StreamArgs, StreamEvent, Usage,
+    },
+};
+
+/// One step of a scripted model response.
+pub enum Step {
+    Event(StreamEvent),
+    /// Fails the model call with the given error.
+    Fail(strands::Error),
+    /// Stops responding without ending the stream.
+    Hang,
+}
+
+/// A model call received by [`ScriptedModelProvider`].
+#[derive(Clone, Debug)]
+pub struct Call {
+    pub messages: Vec<Message>,
+    pub args: StreamArgs,
+}
+
+/// Replays one scripted response per model call and records every call it receives.
+pub struct ScriptedModelProvider {
+    responses: Mutex<VecDeque<Vec<Step>>>,
+    pub calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl ScriptedModelProvider {
+    pub fn new(responses: impl IntoIterator<Item = Vec<Step>>) -> Self {
+        Self {
+            responses: Mutex::new(responses.into_iter().collect()),
+            calls: Arc::default(),
+        }
+    }
+}
+
+impl ModelProvider for ScriptedModelProvider {
+    fn stream(&self, messages: &[Message], args: &StreamArgs) -> ModelProviderStream {
+        self.calls.lock().unwrap().push(Call {
+            messages: messages.to_vec(),
+            args: args.clone(),
+        });
+
+        let steps = self
+            .responses
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("no scripted response left");
+
+        Box::pin(async_stream::stream! {
+            for step in steps {
+                match step {
+                    Step::Event(event) => yield Ok(event),
+                    Step::Fail(error) => {
+                        yield Err(Box::new(error) as ModelProviderError);
+                        return;
+                    }
+                    Step::Hang => futures::future::pending::<()>().await,
+                }
+            }
+        })
+    }
+}
+
+/// Streams `content` the way a provider would, followed by `usage` and the complete message.
+pub fn response(content: Vec<ContentBlock>, stop_reason: StopReason, usage: Usage) -> Vec<Step> {
+    let mut events = vec![StreamEvent::MessageStart {
+        role: Role::Assistant,
+    }];
+
+    for (index, block) in content.iter().enumerate() {
+        match block {
+            ContentBlock::Text(TextBlock(text)) => {
+                events.push(StreamEvent::TextStart { index });
+                events.push(StreamEvent::TextDelta {
+                    index,
+                    delta: text.clone(),
+                });
+            }
+            ContentBlock::ToolUse(tool_use) => {
+                events.push(StreamEvent::ToolUseStart {
+                    index,
+                    id: tool_use.id.clone(),
+                    name: tool_use.name.clone(),
+                });
+                events.push(StreamEvent::ToolInputDelta {
+                    index,
+                    delta: tool_use.input.to_string(),
+                });
+            }
+            _ => {}
+        }
+
+        events.push(StreamEvent::ContentBlockComplete {
+            index,
+            block: block.clone(),
+        });
+    }
+
+    events.push(StreamEvent::Metadata {
+        usage,
+        latency: Duration::from_millis(10),
+    });
+    events.push(StreamEvent::MessageComplete {
+        message: Message {
+            role: Role::Assistant,
+            content,
+        },
+        stop_reason,
+    });
+
+    events.into_iter().map(Step::Event).collect()
+}
+
+/// A response that ends the turn with a single text block.
+pub fn reply(text: &str) -> Vec<Step> {
+    response(
+        vec![self::text(text)],
+        StopReason::EndTurn,
+        Usage::default(),
+    )
+}
+
+/// A response that calls the given tools.
+pub fn tool_calls(calls: impl IntoIterator<Item = ContentBlock>) -> Vec<Step> {
+    response(
+        calls.into_iter().collect(),
+        StopReason::ToolUse,
+        Usage::default(),
+    )
+}
+
+pub fn text(text: &str) -> ContentBlock {
+    ContentBlock::Text(TextBlock(text.into()))
+}
+
+pub fn tool_use(id: &str, name: &str, input: serde_json::Value) -> ContentBlock {
+    ContentBlock::ToolUse(ToolUseBlock {
+        id: id.into(),
+        name: name.into(),
+        input,
+    })
+}
+
+pub fn usage(input_tokens: u64, output_tokens: u64, cache_read_tokens: u64) -> Usage {
+    Usage {
+        input_tokens,
+        output_tokens,
+        cache_read_tokens,
+        cache_write_tokens: 0,
+    }
+}
+
+/// Collects every event of a turn, failing the test on errors.
+pub async fn events(stream: AgentStream) -> Vec<AgentEvent> {
+    stream.map(Result::unwrap).collect().await
+}
+
+/// Runs a turn to completion and returns its events.
+pub async fn run_turn(stream: AgentStream) -> Vec<AgentEvent> {
+    let events = events(stream).await;
+    assert!(
+        matches!(events.last(), Some(AgentEvent::TurnCompleted { .. })),
+        "turn did not complete: {events:?}"
+    );
+
+    events
+}
+
+/// Checks that every tool use is immediately followed by a message answering it.
+pub fn assert_tool_uses_answered(messages: &[Message]) {
+    for (index, message) in messages.iter().enumerate() {
+        for block in &message.content {
+            let ContentBlock::ToolUse(tool_use) = block else {
+                continue;
+            };
+
+            let answered = messages.get(index + 1).is_some_and(|next| {
+                next.content.iter().any(
+                    |block| matches!(block, ContentBlock::ToolResult(result) if result.id == tool_use.id),
+                )
+            });
+            assert!(
+                answered,
+                "tool use {} has no result: {messages:?}",
+                tool_use.id
+            );
+        }
+    }
+}
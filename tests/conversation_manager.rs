@@ -0,0 +1,271 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::model::{
+    ScriptedModelProvider, Step, assert_tool_uses_answered, reply, response, run_turn, text,
+    tool_use, usage,
+};
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    Error,
+    agent::{Agent, AgentArgs},
+    conversation_manager::{
+        ConversationManager, SlidingWindowConversationManager, SummarizingConversationManager,
+    },
+    message::{
+        ContentBlock, Message, Role, StopReason, SystemPrompt, TextBlock, ToolResultBlock,
+        ToolResultContent,
+    },
+    model::model_provider::{ModelPricing, ToolPolicy},
+};
+
+fn assistant(content: ContentBlock) -> Message {
+    Message {
+        role: Role::Assistant,
+        content: vec![content],
+    }
+}
+
+fn tool_result(id: &str) -> Message {
+    Message {
+        role: Role::User,
+        content: vec![ContentBlock::ToolResult(ToolResultBlock {
+            id: id.into(),
+            content: Ok(vec![ToolResultContent::Text(TextBlock("Sunny".into()))]),
+        })],
+    }
+}
+
+/// Two questions, each answered after a tool call.
+fn conversation() -> Vec<Message> {
+    vec![
+        Message::new_user("Weather in Oslo?"),
+        assistant(tool_use("call_1", "weather", json!({ "city": "Oslo" }))),
+        tool_result("call_1"),
+        assistant(text("Sunny in Oslo.")),
+        Message::new_user("Weather in Bergen?"),
+        assistant(tool_use("call_2", "weather", json!({ "city": "Bergen" }))),
+        tool_result("call_2"),
+    ]
+}
+
+/// One prompt answered after `calls` tool calls in a row.
+fn tool_loop(calls: usize) -> Vec<Message> {
+    let mut messages = vec![Message::new_user("Weather along the coast?")];
+    for i in 0..calls {
+        let id = format!("call_{i}");
+        messages.push(assistant(tool_use(&id, "weather", json!({ "stop": i }))));
+        messages.push(tool_result(&id));
+    }
+    messages
+}
+
+fn first_text(message: &Message) -> &str {
+    match &message.content[0] {
+        ContentBlock::Text(TextBlock(text)) => text,
+        other => panic!("unexpected content: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn sliding_window_never_starts_on_a_tool_result() {
+    let provider = ScriptedModelProvider::new([reply("Sunny in Bergen.")]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: conversation(),
+            // The last five messages start on the result of `call_1`.
+            conversation_manager: Some(SlidingWindowConversationManager { window_size: 5 }.boxed()),
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let messages = &calls.lock().unwrap()[0].messages;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(first_text(&messages[0]), "Weather in Bergen?");
+    assert_tool_uses_answered(messages);
+}
+
+#[tokio::test]
+async fn sliding_window_shrinks_the_conversation_on_overflow() {
+    let provider = ScriptedModelProvider::new([
+        vec![Step::Fail(Error::ContextWindowOverflow)],
+        reply("Sunny in Bergen."),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: conversation(),
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls[0].messages.len(), 7);
+    assert_eq!(calls[1].messages.len(), 3);
+    assert_eq!(first_text(&calls[1].messages[0]), "Weather in Bergen?");
+    assert_eq!(agent.messages().len(), 4);
+}
+
+#[tokio::test]
+async fn sliding_window_drops_tool_calls_of_a_long_tool_loop() {
+    let provider = ScriptedModelProvider::new([
+        vec![Step::Fail(Error::ContextWindowOverflow)],
+        reply("Sunny all along."),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: tool_loop(10),
+            conversation_manager: Some(SlidingWindowConversationManager { window_size: 6 }.boxed()),
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let calls = calls.lock().unwrap();
+    // The window keeps the prompt and the last two tool calls, then shrinks on overflow.
+    assert_eq!(calls[0].messages.len(), 5);
+    assert_eq!(calls[1].messages.len(), 3);
+    for call in calls.iter() {
+        assert_eq!(first_text(&call.messages[0]), "Weather along the coast?");
+        assert!(matches!(call.messages[1].role, Role::Assistant));
+        assert_tool_uses_answered(&call.messages);
+    }
+}
+
+fn summarizing_agent(provider: ScriptedModelProvider, pricing: Option<ModelPricing>) -> Agent<()> {
+    Agent::new(
+        provider,
+        AgentArgs {
+            messages: conversation(),
+            conversation_manager: Some(
+                SummarizingConversationManager {
+                    max_messages: 4,
+                    preserve_recent_messages: 3,
+                    summary_prompt: "Summarize.".into(),
+                }
+                .boxed(),
+            ),
+            pricing,
+            spend_limit: pricing.map(|_| 1.0),
+            ..Default::default()
+        },
+    )
+}
+
+#[tokio::test]
+async fn summarizes_older_messages_without_tools() {
+    let provider = ScriptedModelProvider::new([
+        response(
+            vec![text("Oslo is sunny.")],
+            StopReason::EndTurn,
+            usage(100, 10, 0),
+        ),
+        reply("Sunny in Bergen."),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = summarizing_agent(provider, None);
+
+    run_turn(agent.turn()).await;
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 2);
+
+    // The summary covers everything before the preserved turn and may not call tools.
+    let summary_call = &calls[0];
+    assert_eq!(summary_call.messages.len(), 5);
+    assert!(matches!(
+        &summary_call.args.system_prompt,
+        Some(SystemPrompt::Text(prompt)) if prompt == "Summarize."
+    ));
+    assert!(matches!(
+        summary_call.args.tool_policy,
+        Some(ToolPolicy::None)
+    ));
+
+    let messages = &calls[1].messages;
+    assert_eq!(messages.len(), 3);
+    assert_eq!(
+        first_text(&messages[0]),
+        "Summary of the earlier conversation:\nOslo is sunny."
+    );
+    assert_tool_uses_answered(messages);
+
+    // The summarization call is part of the turn's metrics.
+    let metrics = agent.turn_metrics();
+    assert_eq!(metrics.model_calls, 2);
+    assert_eq!(metrics.usage, usage(100, 10, 0));
+}
+
+#[tokio::test]
+async fn summarizes_the_start_of_a_long_tool_loop() {
+    let provider = ScriptedModelProvider::new([reply("Sunny so far."), reply("Sunny all along.")]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: tool_loop(10),
+            conversation_manager: Some(
+                SummarizingConversationManager {
+                    max_messages: 6,
+                    preserve_recent_messages: 4,
+                    summary_prompt: "Summarize.".into(),
+                }
+                .boxed(),
+            ),
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls[0].messages.len(), 18);
+
+    let messages = &calls[1].messages;
+    assert_eq!(messages.len(), 5);
+    assert_eq!(
+        first_text(&messages[0]),
+        "Summary of the earlier conversation:\nSunny so far."
+    );
+    assert!(matches!(messages[1].role, Role::Assistant));
+    assert_tool_uses_answered(messages);
+}
+
+#[tokio::test]
+async fn counts_summarization_towards_the_spend_limit() {
+    let provider = ScriptedModelProvider::new([response(
+        vec![text("Oslo is sunny.")],
+        StopReason::EndTurn,
+        usage(1_000_000, 0, 0),
+    )]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = summarizing_agent(
+        provider,
+        Some(ModelPricing {
+            input: 1.0,
+            ..Default::default()
+        }),
+    );
+
+    let results: Vec<_> = agent.turn().collect().await;
+
+    assert_eq!(calls.lock().unwrap().len(), 1);
+    assert_eq!(agent.metrics().cost, 1.0);
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Spend limit of $1.00 exceeded with $1.00 spent"
+    );
+}
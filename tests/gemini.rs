@@ -0,0 +1,181 @@
+mod common;
+
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    message::{
+        ContentBlock, DocumentBlock, DocumentFormat, DocumentSource, ImageBlock, ImageFormat,
+        ImageSource, JsonBlock, Message, ReasoningBlock, Role, StopReason, SystemPrompt, TextBlock,
+        ToolResultBlock, ToolResultContent, ToolUseBlock,
+    },
+    model::{
+        gemini::GeminiModelProvider,
+        model_provider::{ModelProvider, StreamArgs, StreamEvent, ToolPolicy, Usage},
+    },
+    tool::ToolSpec,
+};
+
+#[tokio::test]
+async fn streams_text_and_function_calls() {
+    let (base_url, request) = common::serve_once(
+        "text/event-stream",
+        concat!(
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"Let me \"}]}}]}\r\n\r\n",
+            "data: {\"candidates\":[{\"content\":{\"role\":\"model\",\"parts\":[{\"text\":\"check.\"},{\"functionCall\":{\"name\":\"weather\",\"args\":{\"city\":\"Oslo\"}},\"thoughtSignature\":\"c2ln\"},{\"functionCall\":{\"name\":\"weather\",\"args\":{\"city\":\"Bergen\"}}}]},\"finishReason\":\"STOP\"}],\"usageMetadata\":{\"promptTokenCount\":40,\"cachedContentTokenCount\":32,\"candidatesTokenCount\":9,\"thoughtsTokenCount\":3}}\r\n\r\n",
+        ),
+    )
+    .await;
+
+    let provider = GeminiModelProvider::new("key", "gemini-2.5-flash").with_base_url(base_url);
+    let mut args = StreamArgs::default();
+    args.system_prompt = Some(SystemPrompt::Text("Be brief.".into()));
+    args.tool_specs = Some(vec![ToolSpec {
+        name: "weather".into(),
+        input_schema: json!({ "type": "object" }).as_object().unwrap().clone(),
+        ..Default::default()
+    }]);
+    args.tool_policy = Some(ToolPolicy::Specific {
+        name: "weather".into(),
+    });
+    args.max_tokens = Some(256);
+
+    let messages = [
+        Message {
+            role: Role::User,
+            content: vec![
+                ContentBlock::Text(TextBlock("Compare these.".into())),
+                ContentBlock::Image(ImageBlock {
+                    format: ImageFormat::Png,
+                    source: ImageSource::Bytes(b"png".to_vec()),
+                }),
+                ContentBlock::Document(DocumentBlock {
+                    name: "report".into(),
+                    format: DocumentFormat::Pdf,
+                    source: DocumentSource::Bytes(b"pdf".to_vec()),
+                    citations: false,
+                    context: None,
+                }),
+            ],
+        },
+        Message {
+            role: Role::Assistant,
+            content: vec![
+                ContentBlock::Reasoning(ReasoningBlock {
+                    text: String::new(),
+                    signature: "c2ln".into(),
+                    redacted: Vec::new(),
+                }),
+                ContentBlock::ToolUse(ToolUseBlock {
+                    id: "call_0".into(),
+                    name: "weather".into(),
+                    input: json!({ "city": "Bergen" }),
+                }),
+            ],
+        },
+        Message {
+            role: Role::User,
+            content: vec![ContentBlock::ToolResult(ToolResultBlock {
+                id: "call_0".into(),
+                content: Ok(vec![ToolResultContent::Json(JsonBlock(
+                    json!({ "rain": true }),
+                ))]),
+            })],
+        },
+    ];
+
+    let events: Vec<StreamEvent> = provider
+        .stream(&messages, &args)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert!(matches!(events[0], StreamEvent::MessageStart { .. }));
+    assert!(matches!(events[1], StreamEvent::TextStart { index: 0 }));
+    assert!(matches!(
+        &events[5],
+        StreamEvent::ReasoningStart { index: 1 }
+    ));
+    assert!(matches!(
+        &events[8],
+        StreamEvent::ToolUseStart { index: 2, name, .. } if name == "weather"
+    ));
+
+    assert!(matches!(
+        &events[events.len() - 2],
+        StreamEvent::Metadata { usage, .. } if *usage == Usage {
+            input_tokens: 8,
+            output_tokens: 12,
+            cache_read_tokens: 32,
+            cache_write_tokens: 0,
+        }
+    ));
+
+    let Some(StreamEvent::MessageComplete {
+        message,
+        stop_reason,
+    }) = events.last()
+    else {
+        panic!("stream did not complete the message");
+    };
+
+    assert!(matches!(stop_reason, StopReason::ToolUse));
+    assert!(matches!(&message.content[0], ContentBlock::Text(text) if text.0 == "Let me check."));
+    // The thought signature is kept in a reasoning block of its own.
+    assert!(matches!(
+        &message.content[1],
+        ContentBlock::Reasoning(reasoning) if reasoning.text.is_empty() && reasoning.signature == "c2ln"
+    ));
+    let (ContentBlock::ToolUse(first), ContentBlock::ToolUse(second)) =
+        (&message.content[2], &message.content[3])
+    else {
+        panic!("expected two tool uses");
+    };
+    assert_eq!(first.input, json!({ "city": "Oslo" }));
+    assert_eq!(second.input, json!({ "city": "Bergen" }));
+    assert_ne!(first.id, second.id);
+
+    let request = request.await.unwrap();
+    assert!(
+        request
+            .head
+            .starts_with("post /models/gemini-2.5-flash:streamgeneratecontent?alt=sse http/1.1")
+    );
+    assert!(request.head.contains("x-goog-api-key: key"));
+
+    assert_eq!(
+        request.body,
+        json!({
+            "contents": [
+                {
+                    "role": "user",
+                    "parts": [
+                        { "text": "Compare these." },
+                        { "inlineData": { "mimeType": "image/png", "data": "cG5n" } },
+                        { "inlineData": { "mimeType": "application/pdf", "data": "cGRm" } },
+                    ],
+                },
+                {
+                    "role": "model",
+                    "parts": [{
+                        "functionCall": { "name": "weather", "args": { "city": "Bergen" } },
+                        "thoughtSignature": "c2ln",
+                    }],
+                },
+                {
+                    "role": "user",
+                    "parts": [{
+                        "functionResponse": { "name": "weather", "response": { "output": { "rain": true } } },
+                    }],
+                },
+            ],
+            "systemInstruction": { "parts": [{ "text": "Be brief." }] },
+            "tools": [{
+                "functionDeclarations": [{ "name": "weather", "parametersJsonSchema": { "type": "object" } }],
+            }],
+            "toolConfig": {
+                "functionCallingConfig": { "mode": "ANY", "allowedFunctionNames": ["weather"] },
+            },
+            "generationConfig": { "maxOutputTokens": 256 },
+        })
+    );
+}
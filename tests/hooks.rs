@@ -0,0 +1,189 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use common::model::{ScriptedModelProvider, reply, run_turn, tool_calls, tool_use};
+use serde_json::json;
+use strands::{
+    agent::{Agent, AgentArgs},
+    hook::{Hook, ToolCallDecision},
+    message::{
+        ContentBlock, Message, Role, TextBlock, ToolResult, ToolResultBlock, ToolResultContent,
+        ToolUseBlock,
+    },
+    tool::{Tool, ToolContext, ToolSpec},
+};
+
+/// Answers with the input it was called with.
+struct EchoTool;
+
+#[async_trait::async_trait]
+impl Tool<()> for EchoTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "echo".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        Ok(Ok(vec![text(&json!(input).to_string())]))
+    }
+}
+
+/// Vetoes calls without a `city`, rewrites the city of the others and appends a note to every
+/// result.
+struct PolicyHook;
+
+#[async_trait::async_trait]
+impl Hook for PolicyHook {
+    async fn before_tool_call(
+        &self,
+        _tool_use: &ToolUseBlock,
+        input: &mut serde_json::Value,
+    ) -> ToolCallDecision {
+        match input.get("city") {
+            Some(_) => {
+                input["city"] = json!("Bergen");
+                ToolCallDecision::Continue
+            }
+            None => ToolCallDecision::Veto(Err(vec![text("A city is required")])),
+        }
+    }
+
+    async fn after_tool_call(&self, tool_use: &ToolUseBlock, result: &mut ToolResult) {
+        let content = result.as_mut().unwrap_or_else(|e| e);
+        content.push(text(&format!("Checked {}", tool_use.id)));
+    }
+}
+
+/// Records the order in which messages are appended to the conversation.
+#[derive(Default)]
+struct RecordingHook {
+    appended: Arc<Mutex<Vec<String>>>,
+}
+
+#[async_trait::async_trait]
+impl Hook for RecordingHook {
+    async fn message_appended(&self, message: &Message) {
+        let role = match message.role {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+            _ => "other",
+        };
+        let blocks: Vec<_> = message
+            .content
+            .iter()
+            .map(|block| match block {
+                ContentBlock::Text(TextBlock(text)) => text.clone(),
+                ContentBlock::ToolUse(tool_use) => format!("tool_use {}", tool_use.id),
+                ContentBlock::ToolResult(result) => format!("tool_result {}", result.id),
+                other => format!("{other:?}"),
+            })
+            .collect();
+        self.appended
+            .lock()
+            .unwrap()
+            .push(format!("{role}: {}", blocks.join(", ")));
+    }
+}
+
+fn text(text: &str) -> ToolResultContent {
+    ToolResultContent::Text(TextBlock(text.into()))
+}
+
+fn texts(result: &ToolResultBlock) -> Vec<&str> {
+    let content = result.content.as_ref().unwrap_or_else(|e| e);
+    content
+        .iter()
+        .map(|content| match content {
+            ToolResultContent::Text(TextBlock(text)) => text.as_str(),
+            other => panic!("unexpected tool result content: {other:?}"),
+        })
+        .collect()
+}
+
+fn tool_results(message: &Message) -> Vec<&ToolResultBlock> {
+    message
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult(result) => Some(result),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn rewrites_vetoes_and_replaces_tool_calls() {
+    let provider = ScriptedModelProvider::new([
+        tool_calls([
+            tool_use("call_1", "echo", json!({ "city": "Oslo" })),
+            tool_use("call_2", "echo", json!({})),
+        ]),
+        reply("Done."),
+    ]);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Echo")],
+            tools: vec![EchoTool.boxed()],
+            hooks: vec![PolicyHook.boxed()],
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let messages = agent.messages();
+    let results = tool_results(&messages[2]);
+    assert_eq!(results.len(), 2);
+
+    // The rewritten input reaches the tool, but the id of the tool use stays the same.
+    assert_eq!(results[0].id, "call_1");
+    assert!(results[0].content.is_ok());
+    assert_eq!(
+        texts(results[0]),
+        [r#"{"city":"Bergen"}"#, "Checked call_1"]
+    );
+
+    assert_eq!(results[1].id, "call_2");
+    assert!(results[1].content.is_err());
+    assert_eq!(texts(results[1]), ["A city is required", "Checked call_2"]);
+}
+
+#[tokio::test]
+async fn reports_appended_messages_in_order() {
+    let provider = ScriptedModelProvider::new([
+        tool_calls([tool_use("call_1", "echo", json!({}))]),
+        reply("Done."),
+    ]);
+    let hook = RecordingHook::default();
+    let appended = Arc::clone(&hook.appended);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Echo")],
+            tools: vec![EchoTool.boxed()],
+            hooks: vec![hook.boxed()],
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    assert_eq!(
+        *appended.lock().unwrap(),
+        [
+            "assistant: tool_use call_1",
+            "user: tool_result call_1",
+            "assistant: Done.",
+        ]
+    );
+}
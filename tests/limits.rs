@@ -0,0 +1,204 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::model::{
+    ScriptedModelProvider, Step, assert_tool_uses_answered, events, response, text, tool_calls,
+    tool_use, usage,
+};
+use serde_json::json;
+use strands::{
+    agent::{Agent, AgentArgs, AgentEvent, TurnLimit, TurnLimits},
+    message::{ContentBlock, Message, Role, StopReason, TextBlock, ToolResult, ToolResultContent},
+    model::model_provider::{StreamEvent, Usage},
+    tool::{Tool, ToolContext, ToolSpec},
+};
+
+struct WeatherTool;
+
+#[async_trait::async_trait]
+impl Tool<()> for WeatherTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "weather".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        _input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        Ok(Ok(vec![ToolResultContent::Text(TextBlock("Sunny".into()))]))
+    }
+}
+
+fn weather_agent(provider: ScriptedModelProvider, limits: TurnLimits) -> Agent<()> {
+    Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Weather in Oslo?")],
+            tools: vec![WeatherTool.boxed()],
+            limits,
+            ..Default::default()
+        },
+    )
+}
+
+fn weather_call(id: &str, output_tokens: u64) -> Vec<Step> {
+    response(
+        vec![tool_use(id, "weather", json!({}))],
+        StopReason::ToolUse,
+        usage(0, output_tokens, 0),
+    )
+}
+
+fn limit_reached(events: &[AgentEvent]) -> Option<TurnLimit> {
+    match events.last() {
+        Some(AgentEvent::LimitReached { limit }) => Some(*limit),
+        _ => None,
+    }
+}
+
+#[tokio::test]
+async fn stops_after_the_maximum_number_of_model_calls() {
+    let provider = ScriptedModelProvider::new([weather_call("call_1", 0)]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = weather_agent(
+        provider,
+        TurnLimits {
+            max_model_calls: Some(1),
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+
+    assert!(matches!(
+        limit_reached(&events),
+        Some(TurnLimit::ModelCalls)
+    ));
+    assert_eq!(calls.lock().unwrap().len(), 1);
+    assert_tool_uses_answered(&agent.messages());
+}
+
+#[tokio::test]
+async fn answers_tool_calls_beyond_the_maximum_with_errors() {
+    let provider = ScriptedModelProvider::new([tool_calls([
+        tool_use("call_1", "weather", json!({})),
+        tool_use("call_2", "weather", json!({})),
+    ])]);
+    let mut agent = weather_agent(
+        provider,
+        TurnLimits {
+            max_tool_calls: Some(1),
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+
+    assert!(matches!(limit_reached(&events), Some(TurnLimit::ToolCalls)));
+    assert_eq!(agent.turn_metrics().tool_calls, 1);
+
+    let messages = agent.messages();
+    assert_tool_uses_answered(&messages);
+    let results: Vec<_> = messages[2]
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult(result) => Some(result),
+            _ => None,
+        })
+        .collect();
+    assert!(results[0].content.is_ok());
+    assert!(results[1].content.is_err());
+}
+
+#[tokio::test]
+async fn limits_output_tokens_by_the_reported_usage() {
+    let provider =
+        ScriptedModelProvider::new([weather_call("call_1", 100), weather_call("call_2", 60)]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = weather_agent(
+        provider,
+        TurnLimits {
+            max_output_tokens: Some(150),
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+
+    assert!(matches!(
+        limit_reached(&events),
+        Some(TurnLimit::OutputTokens)
+    ));
+
+    // Each call may only produce the tokens left in the budget.
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 2);
+    assert_eq!(calls[0].args.max_tokens, Some(150));
+    assert_eq!(calls[1].args.max_tokens, Some(50));
+}
+
+#[tokio::test]
+async fn estimates_output_tokens_without_reported_usage() {
+    let provider = ScriptedModelProvider::new([
+        response(
+            vec![text(&"a".repeat(400))],
+            StopReason::MaxTokens,
+            Usage::default(),
+        ),
+        response(
+            vec![text("The end.")],
+            StopReason::EndTurn,
+            Usage::default(),
+        ),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Tell me a story")],
+            max_tokens_continuations: 1,
+            limits: TurnLimits {
+                max_output_tokens: Some(1000),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::TurnCompleted { .. })
+    ));
+    assert_eq!(calls.lock().unwrap()[1].args.max_tokens, Some(900));
+}
+
+#[tokio::test]
+async fn stops_a_hanging_model_call_at_the_deadline() {
+    let provider = ScriptedModelProvider::new([vec![
+        Step::Event(StreamEvent::MessageStart {
+            role: Role::Assistant,
+        }),
+        Step::Hang,
+    ]]);
+    let mut agent = weather_agent(
+        provider,
+        TurnLimits {
+            deadline: Some(Duration::from_millis(50)),
+            ..Default::default()
+        },
+    );
+
+    let events = events(agent.turn()).await;
+
+    assert!(matches!(limit_reached(&events), Some(TurnLimit::Deadline)));
+    // The partial response is discarded.
+    assert_eq!(agent.messages().len(), 1);
+}
@@ -0,0 +1,99 @@
+mod common;
+
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    message::{
+        ContentBlock, ImageBlock, ImageFormat, ImageSource, Message, Role, StopReason, TextBlock,
+    },
+    model::{
+        model_provider::{ModelProvider, StreamArgs, StreamEvent},
+        ollama::OllamaModelProvider,
+    },
+    tool::ToolSpec,
+};
+
+#[tokio::test]
+async fn streams_text_and_tool_calls() {
+    let (base_url, request) = common::serve_once(
+        "application/x-ndjson",
+        concat!(
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"Let me \"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"check.\"},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\",\"tool_calls\":[{\"function\":{\"name\":\"weather\",\"arguments\":{\"city\":\"Oslo\"}}}]},\"done\":false}\n",
+            "{\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":26,\"eval_count\":12}\n",
+        ),
+    )
+    .await;
+
+    let provider = OllamaModelProvider::new(base_url, "llama3.2");
+    let mut args = StreamArgs::default();
+    args.tool_specs = Some(vec![ToolSpec {
+        name: "weather".into(),
+        input_schema: json!({ "type": "object" }).as_object().unwrap().clone(),
+        ..Default::default()
+    }]);
+    args.temperature = Some(0.5);
+    args.stop_sequences = Some(vec!["END".into()]);
+    args.max_tokens = Some(128);
+
+    let message = Message {
+        role: Role::User,
+        content: vec![
+            ContentBlock::Text(TextBlock("What is in this image?".into())),
+            ContentBlock::Image(ImageBlock {
+                format: ImageFormat::Png,
+                source: ImageSource::Bytes(b"png".to_vec()),
+            }),
+        ],
+    };
+
+    let events: Vec<StreamEvent> = provider
+        .stream(&[message], &args)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert!(matches!(events[0], StreamEvent::MessageStart { .. }));
+    assert!(matches!(events[1], StreamEvent::TextStart { index: 0 }));
+    assert!(matches!(
+        &events[5],
+        StreamEvent::ToolUseStart { index: 1, name, .. } if name == "weather"
+    ));
+
+    assert!(matches!(
+        &events[events.len() - 2],
+        StreamEvent::Metadata { usage, .. } if usage.input_tokens == 26 && usage.output_tokens == 12
+    ));
+
+    let Some(StreamEvent::MessageComplete {
+        message,
+        stop_reason,
+    }) = events.last()
+    else {
+        panic!("stream did not complete the message");
+    };
+
+    assert!(matches!(stop_reason, StopReason::ToolUse));
+    assert!(matches!(&message.content[0], ContentBlock::Text(text) if text.0 == "Let me check."));
+    assert!(matches!(
+        &message.content[1],
+        ContentBlock::ToolUse(tool_use) if tool_use.input == json!({ "city": "Oslo" })
+    ));
+
+    let request = request.await.unwrap();
+    assert!(request.head.starts_with("post /api/chat http/1.1"));
+
+    let request = request.body;
+    assert_eq!(request["model"], "llama3.2");
+    assert_eq!(request["stream"], true);
+    assert_eq!(
+        request["options"],
+        json!({ "temperature": 0.5, "stop": ["END"], "num_predict": 128 })
+    );
+    assert_eq!(
+        request["messages"],
+        json!([{ "role": "user", "content": "What is in this image?", "images": ["cG5n"] }])
+    );
+    assert_eq!(request["tools"][0]["function"]["name"], "weather");
+}
@@ -0,0 +1,161 @@
+mod common;
+
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    message::{ContentBlock, Message, StopReason},
+    model::{
+        model_provider::{ModelProvider, StreamArgs, StreamEvent, ToolPolicy, Usage},
+        openai::OpenAiModelProvider,
+    },
+    tool::ToolSpec,
+};
+
+#[tokio::test]
+async fn streams_text_and_tool_calls() {
+    let (base_url, request) = common::serve_once("text/event-stream", concat!(
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"Let me check.\"}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"weather\",\"arguments\":\"\"}}]}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\"\\\"Oslo\\\"}\"}}]}}]}\n\n",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+        "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":20,\"completion_tokens\":8,\"prompt_tokens_details\":{\"cached_tokens\":5}}}\n\n",
+        "data: [DONE]\n\n",
+    ))
+    .await;
+
+    let provider =
+        OpenAiModelProvider::new(format!("{base_url}/v1"), "local-model").with_api_key("sk-test");
+    let mut args = StreamArgs::default();
+    args.tool_specs = Some(vec![ToolSpec {
+        name: "weather".into(),
+        description: Some("Looks up the weather.".into()),
+        input_schema: json!({ "type": "object" }).as_object().unwrap().clone(),
+        ..Default::default()
+    }]);
+    args.tool_policy = Some(ToolPolicy::Required);
+    args.parallel_tool_use = Some(false);
+    args.max_tokens = Some(256);
+
+    let events: Vec<StreamEvent> = provider
+        .stream(&[Message::new_user("Weather in Oslo?")], &args)
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert!(matches!(events[0], StreamEvent::MessageStart { .. }));
+    assert!(matches!(events[1], StreamEvent::TextStart { index: 0 }));
+    assert!(matches!(
+        &events[4],
+        StreamEvent::ToolUseStart { index: 1, id, name } if id == "call_1" && name == "weather"
+    ));
+
+    let input: String = events
+        .iter()
+        .filter_map(|event| match event {
+            StreamEvent::ToolInputDelta { index: 1, delta } => Some(delta.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(input, "{\"city\":\"Oslo\"}");
+
+    assert!(matches!(
+        &events[events.len() - 2],
+        StreamEvent::Metadata { usage, .. } if *usage == Usage {
+            input_tokens: 15,
+            output_tokens: 8,
+            cache_read_tokens: 5,
+            cache_write_tokens: 0,
+        }
+    ));
+
+    let Some(StreamEvent::MessageComplete {
+        message,
+        stop_reason,
+    }) = events.last()
+    else {
+        panic!("stream did not complete the message");
+    };
+
+    assert!(matches!(stop_reason, StopReason::ToolUse));
+    assert!(matches!(&message.content[0], ContentBlock::Text(text) if text.0 == "Let me check."));
+    assert!(matches!(
+        &message.content[1],
+        ContentBlock::ToolUse(tool_use) if tool_use.input == json!({ "city": "Oslo" })
+    ));
+
+    let request = request.await.unwrap();
+    assert!(
+        request
+            .head
+            .starts_with("post /v1/chat/completions http/1.1")
+    );
+    assert!(request.head.contains("authorization: bearer sk-test"));
+
+    let request = request.body;
+    assert_eq!(request["model"], "local-model");
+    assert_eq!(request["stream"], true);
+    assert_eq!(request["stream_options"], json!({ "include_usage": true }));
+    assert_eq!(request["max_tokens"], 256);
+    assert_eq!(request["tool_choice"], "required");
+    assert_eq!(request["parallel_tool_calls"], false);
+    assert_eq!(
+        request["messages"],
+        json!([{ "role": "user", "content": "Weather in Oslo?" }])
+    );
+    assert_eq!(request["tools"][0]["type"], "function");
+    assert_eq!(request["tools"][0]["function"]["name"], "weather");
+}
+
+#[tokio::test]
+async fn truncated_stream_does_not_complete() {
+    let (base_url, _request) = common::serve_once(
+        "text/event-stream",
+        "data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+    )
+    .await;
+
+    let provider = OpenAiModelProvider::new(format!("{base_url}/v1"), "local-model");
+    let events: Vec<StreamEvent> = provider
+        .stream(&[Message::new_user("Hi")], &StreamArgs::default())
+        .map(Result::unwrap)
+        .collect()
+        .await;
+
+    assert!(
+        !events
+            .iter()
+            .any(|event| matches!(event, StreamEvent::MessageComplete { .. }))
+    );
+}
+
+#[tokio::test]
+async fn assigns_unique_ids_to_tool_calls_without_one() {
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let (base_url, _request) = common::serve_once("text/event-stream", concat!(
+            "data: {\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"type\":\"function\",\"function\":{\"name\":\"weather\",\"arguments\":\"{}\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        ))
+        .await;
+
+        let provider = OpenAiModelProvider::new(format!("{base_url}/v1"), "local-model");
+        let events: Vec<StreamEvent> = provider
+            .stream(&[Message::new_user("Weather?")], &StreamArgs::default())
+            .map(Result::unwrap)
+            .collect()
+            .await;
+
+        let Some(StreamEvent::MessageComplete { message, .. }) = events.last() else {
+            panic!("stream did not complete the message");
+        };
+        let ContentBlock::ToolUse(tool_use) = &message.content[0] else {
+            panic!("expected a tool use");
+        };
+        ids.push(tool_use.id.clone());
+    }
+
+    // Ids stay unique across the turns of a conversation.
+    assert_ne!(ids[0], ids[1]);
+}
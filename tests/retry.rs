@@ -0,0 +1,183 @@
+mod common;
+
+use std::{sync::Arc, time::Duration};
+
+use common::model::{ScriptedModelProvider, Step, events, reply};
+use futures::StreamExt;
+use strands::{
+    Error,
+    agent::{Agent, AgentArgs, AgentEvent, RetryPolicy, TurnLimit, TurnLimits},
+    message::{Message, Role},
+    model::model_provider::StreamEvent,
+};
+
+fn retry_agent(provider: ScriptedModelProvider, retry: RetryPolicy) -> Agent<()> {
+    Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            retry: Some(retry),
+            ..Default::default()
+        },
+    )
+}
+
+fn quick_retries() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 3,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+    }
+}
+
+fn message_start() -> Step {
+    Step::Event(StreamEvent::MessageStart {
+        role: Role::Assistant,
+    })
+}
+
+fn connection_reset() -> Step {
+    Step::Fail(Error::Io(std::io::Error::new(
+        std::io::ErrorKind::ConnectionReset,
+        "connection reset",
+    )))
+}
+
+fn count(events: &[AgentEvent], matches: impl Fn(&AgentEvent) -> bool) -> usize {
+    events.iter().filter(|event| matches(event)).count()
+}
+
+#[tokio::test]
+async fn retries_transient_errors_without_repeating_events() {
+    let provider = ScriptedModelProvider::new([
+        vec![connection_reset()],
+        vec![message_start(), connection_reset()],
+        reply("Hello."),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = retry_agent(provider, quick_retries());
+
+    let events = events(agent.turn()).await;
+
+    assert_eq!(calls.lock().unwrap().len(), 3);
+    assert_eq!(
+        count(&events, |event| matches!(
+            event,
+            AgentEvent::Model(StreamEvent::MessageStart { .. })
+        )),
+        1
+    );
+    assert_eq!(
+        count(&events, |event| matches!(
+            event,
+            AgentEvent::Model(StreamEvent::MessageComplete { .. })
+        )),
+        1
+    );
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::TurnCompleted { .. })
+    ));
+    assert_eq!(agent.messages().len(), 2);
+}
+
+#[tokio::test]
+async fn retries_truncated_streams() {
+    let provider = ScriptedModelProvider::new([vec![message_start()], reply("Hello.")]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = retry_agent(provider, quick_retries());
+
+    let events = events(agent.turn()).await;
+
+    assert_eq!(calls.lock().unwrap().len(), 2);
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::TurnCompleted { .. })
+    ));
+}
+
+#[tokio::test]
+async fn gives_up_after_the_maximum_number_of_attempts() {
+    let provider = ScriptedModelProvider::new([
+        vec![message_start()],
+        vec![message_start()],
+        vec![message_start()],
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = retry_agent(provider, quick_retries());
+
+    let results: Vec<_> = agent.turn().collect().await;
+
+    assert_eq!(calls.lock().unwrap().len(), 3);
+    let error = results.last().unwrap().as_ref().unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Model provider stream ended without a complete message"
+    );
+}
+
+#[tokio::test]
+async fn does_not_retry_fatal_errors() {
+    let provider = ScriptedModelProvider::new([vec![Step::Fail(Error::ToolExecution(
+        "invalid request".into(),
+    ))]]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = retry_agent(provider, quick_retries());
+
+    let results: Vec<_> = agent.turn().collect().await;
+
+    assert_eq!(calls.lock().unwrap().len(), 1);
+    assert!(results.last().unwrap().is_err());
+}
+
+#[tokio::test]
+async fn does_not_retry_once_content_was_streamed() {
+    let provider = ScriptedModelProvider::new([vec![
+        message_start(),
+        Step::Event(StreamEvent::TextStart { index: 0 }),
+        Step::Event(StreamEvent::TextDelta {
+            index: 0,
+            delta: "Hel".into(),
+        }),
+        connection_reset(),
+    ]]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = retry_agent(provider, quick_retries());
+
+    let results: Vec<_> = agent.turn().collect().await;
+
+    assert_eq!(calls.lock().unwrap().len(), 1);
+    assert!(results.last().unwrap().is_err());
+    assert_eq!(agent.messages().len(), 1);
+}
+
+#[tokio::test]
+async fn stops_backing_off_at_the_deadline() {
+    let provider = ScriptedModelProvider::new([vec![connection_reset()]]);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            retry: Some(RetryPolicy {
+                initial_backoff: Duration::from_secs(60),
+                ..Default::default()
+            }),
+            limits: TurnLimits {
+                deadline: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let events = tokio::time::timeout(Duration::from_secs(5), events(agent.turn()))
+        .await
+        .expect("the backoff outlived the deadline");
+
+    assert!(matches!(
+        events.last(),
+        Some(AgentEvent::LimitReached {
+            limit: TurnLimit::Deadline
+        })
+    ));
+}
@@ -0,0 +1,179 @@
+#![cfg(feature = "serde")]
+
+use serde_json::json;
+use strands::message::{
+    CachePointBlock, ContentBlock, DocumentBlock, DocumentFormat, DocumentSource, GuardBlock,
+    GuardImage, GuardImageFormat, GuardQualifier, GuardText, ImageBlock, ImageFormat, ImageSource,
+    JsonBlock, Message, ReasoningBlock, Role, TextBlock, ToolResultBlock, ToolResultContent,
+    ToolUseBlock, VideoBlock, VideoFormat, VideoSource,
+};
+
+/// Serializes `block`, deserializes it again and checks that nothing was lost.
+fn round_trip(block: ContentBlock) -> serde_json::Value {
+    let encoded = serde_json::to_value(&block).unwrap();
+    let decoded: ContentBlock = serde_json::from_value(encoded.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&decoded).unwrap(), encoded);
+    encoded
+}
+
+#[test]
+fn text() {
+    let encoded = round_trip(ContentBlock::Text(TextBlock("Hello".into())));
+    assert_eq!(encoded, json!({ "type": "text", "value": "Hello" }));
+}
+
+#[test]
+fn tool_use() {
+    let encoded = round_trip(ContentBlock::ToolUse(ToolUseBlock {
+        id: "toolu_1".into(),
+        name: "weather".into(),
+        input: json!({ "city": "Oslo" }),
+    }));
+
+    assert_eq!(
+        encoded,
+        json!({
+            "type": "tool_use",
+            "value": { "id": "toolu_1", "name": "weather", "input": { "city": "Oslo" } },
+        })
+    );
+}
+
+#[test]
+fn tool_result() {
+    let encoded = round_trip(ContentBlock::ToolResult(ToolResultBlock {
+        id: "toolu_1".into(),
+        content: Ok(vec![
+            ToolResultContent::Text(TextBlock("Sunny".into())),
+            ToolResultContent::Json(JsonBlock(json!({ "celsius": 21 }))),
+        ]),
+    }));
+
+    assert_eq!(
+        encoded,
+        json!({
+            "type": "tool_result",
+            "value": {
+                "id": "toolu_1",
+                "status": "success",
+                "content": [
+                    { "type": "text", "value": "Sunny" },
+                    { "type": "json", "value": { "celsius": 21 } },
+                ],
+            },
+        })
+    );
+}
+
+#[test]
+fn tool_result_error() {
+    let encoded = round_trip(ContentBlock::ToolResult(ToolResultBlock {
+        id: "toolu_1".into(),
+        content: Err(vec![ToolResultContent::Text(TextBlock("Timed out".into()))]),
+    }));
+
+    assert_eq!(encoded["value"]["status"], "error");
+}
+
+#[test]
+fn reasoning() {
+    let encoded = round_trip(ContentBlock::Reasoning(ReasoningBlock {
+        text: "Let me think.".into(),
+        signature: "sig".into(),
+        redacted: vec![0, 1, 2, 255],
+    }));
+
+    assert_eq!(encoded["value"]["redacted"], "AAEC/w==");
+}
+
+#[test]
+fn cache_point() {
+    let encoded = round_trip(ContentBlock::CachePoint(CachePointBlock::Default));
+    assert_eq!(
+        encoded,
+        json!({ "type": "cache_point", "value": "default" })
+    );
+}
+
+#[test]
+fn image() {
+    let encoded = round_trip(ContentBlock::Image(ImageBlock {
+        format: ImageFormat::Png,
+        source: ImageSource::Bytes(b"png".to_vec()),
+    }));
+
+    assert_eq!(
+        encoded,
+        json!({
+            "type": "image",
+            "value": { "format": "png", "source": { "type": "bytes", "value": "cG5n" } },
+        })
+    );
+
+    round_trip(ContentBlock::Image(ImageBlock {
+        format: ImageFormat::Jpeg,
+        source: ImageSource::Url("https://example.com/cat.jpg".into()),
+    }));
+}
+
+#[test]
+fn video() {
+    let encoded = round_trip(ContentBlock::Video(VideoBlock {
+        format: VideoFormat::Tgp,
+        source: VideoSource::Bytes(b"video".to_vec()),
+    }));
+
+    assert_eq!(encoded["value"]["format"], "3gp");
+    assert_eq!(encoded["value"]["source"]["value"], "dmlkZW8=");
+}
+
+#[test]
+fn document() {
+    for source in [
+        DocumentSource::Bytes(b"%PDF".to_vec()),
+        DocumentSource::Text("plain".into()),
+        DocumentSource::Structured(vec![TextBlock("a".into()), TextBlock("b".into())]),
+        DocumentSource::Url("https://example.com/report.pdf".into()),
+    ] {
+        round_trip(ContentBlock::Document(DocumentBlock {
+            name: "report".into(),
+            format: DocumentFormat::Pdf,
+            source,
+            citations: true,
+            context: Some("Quarterly report".into()),
+        }));
+    }
+}
+
+#[test]
+fn guard() {
+    let encoded = round_trip(ContentBlock::Guard(GuardBlock::Text(GuardText {
+        qualifiers: vec![GuardQualifier::GroundingSource, GuardQualifier::Query],
+        text: "Check this".into(),
+    })));
+
+    assert_eq!(
+        encoded["value"]["value"]["qualifiers"],
+        json!(["grounding_source", "query"])
+    );
+
+    let encoded = round_trip(ContentBlock::Guard(GuardBlock::Image(GuardImage {
+        format: GuardImageFormat::Jpeg,
+        source: vec![0xff, 0xd8],
+    })));
+
+    assert_eq!(encoded["value"]["value"]["source"], "/9g=");
+}
+
+#[test]
+fn message() {
+    let message = Message {
+        role: Role::Assistant,
+        content: vec![ContentBlock::Text(TextBlock("Hi".into()))],
+    };
+
+    assert_eq!(
+        serde_json::to_value(&message).unwrap(),
+        json!({ "role": "assistant", "content": [{ "type": "text", "value": "Hi" }] })
+    );
+}
@@ -0,0 +1,211 @@
+#![cfg(feature = "serde")]
+
+mod common;
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use common::model::{
+    ScriptedModelProvider, assert_tool_uses_answered, reply, run_turn, tool_calls, tool_use,
+};
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    Error,
+    agent::{Agent, AgentArgs},
+    hook::Hook,
+    message::{ContentBlock, Message, SystemPrompt, ToolResult},
+    session::{FileSessionManager, SessionArgs, SessionManager},
+    tool::{Tool, ToolContext, ToolSpec},
+};
+
+/// Never finishes.
+struct HangingTool;
+
+#[async_trait::async_trait]
+impl Tool<()> for HangingTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "hang".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        _input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        futures::future::pending().await
+    }
+}
+
+/// Records every appended message.
+#[derive(Clone, Default)]
+struct Appended(Arc<Mutex<Vec<Message>>>);
+
+#[async_trait::async_trait]
+impl Hook for Appended {
+    async fn message_appended(&self, message: &Message) {
+        self.0.lock().unwrap().push(message.clone());
+    }
+}
+
+/// A fresh directory for one test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("strands-session-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn session_args(dir: &PathBuf) -> Option<SessionArgs> {
+    let mut metadata = serde_json::Map::new();
+    metadata.insert("user".into(), json!("ada"));
+
+    Some(SessionArgs {
+        id: "chat-1".into(),
+        manager: FileSessionManager::new(dir).boxed(),
+        metadata,
+    })
+}
+
+/// Compares messages by their debug output, as messages do not implement `PartialEq`.
+fn assert_same(actual: &[Message], expected: &[Message]) {
+    assert_eq!(format!("{actual:?}"), format!("{expected:?}"));
+}
+
+#[tokio::test]
+async fn restores_a_saved_session() {
+    let dir = temp_dir("restore");
+    let mut agent = Agent::new(
+        ScriptedModelProvider::new([reply("Hello.")]),
+        AgentArgs {
+            system_prompt: Some(SystemPrompt::new("Be brief.")),
+            messages: vec![Message::new_user("Hi")],
+            session: session_args(&dir),
+            ..Default::default()
+        },
+    );
+    agent.state().set("city", json!("Oslo")).await.unwrap();
+
+    run_turn(agent.turn()).await;
+
+    let manager = FileSessionManager::new(&dir);
+    let saved = manager.load("chat-1").await.unwrap().unwrap();
+    assert_same(&saved.messages, &agent.messages());
+    assert_eq!(saved.state.get("city"), Some(&json!("Oslo")));
+    assert_eq!(saved.metadata.get("user"), Some(&json!("ada")));
+
+    let mut restored: Agent<()> = Agent::from_session(
+        ScriptedModelProvider::new([reply("Still here.")]),
+        AgentArgs::default(),
+        manager.clone(),
+        "chat-1",
+    )
+    .await
+    .unwrap();
+    assert_same(&restored.messages(), &agent.messages());
+    assert_eq!(
+        restored.state().get("city").await.unwrap(),
+        Some(json!("Oslo"))
+    );
+
+    // The restored agent keeps saving to the same session.
+    run_turn(restored.turn()).await;
+    let saved_again = manager.load("chat-1").await.unwrap().unwrap();
+    assert_eq!(saved_again.messages.len(), 3);
+    assert_eq!(saved_again.created_at, saved.created_at);
+    assert!(matches!(
+        &saved_again.system_prompt,
+        SystemPrompt::Text(prompt) if prompt == "Be brief."
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn saves_tool_results_of_a_dropped_turn() {
+    let dir = temp_dir("dropped");
+    let appended = Appended::default();
+    let mut agent = Agent::new(
+        ScriptedModelProvider::new([tool_calls([tool_use("call_1", "hang", json!({}))])]),
+        AgentArgs {
+            messages: vec![Message::new_user("Wait")],
+            tools: vec![HangingTool.boxed()],
+            hooks: vec![Box::new(appended.clone())],
+            session: session_args(&dir),
+            ..Default::default()
+        },
+    );
+
+    let mut stream = agent.turn();
+    while tokio::time::timeout(Duration::from_millis(50), stream.next())
+        .await
+        .is_ok()
+    {}
+    drop(stream);
+
+    // The cancelled results are reported in the background.
+    let manager = FileSessionManager::new(&dir);
+    let mut saved = manager.load("chat-1").await.unwrap().unwrap();
+    for _ in 0..100 {
+        if saved.messages.len() == 3 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        saved = manager.load("chat-1").await.unwrap().unwrap();
+    }
+
+    assert_same(&saved.messages, &agent.messages());
+    assert_tool_uses_answered(&saved.messages);
+
+    let appended = appended.0.lock().unwrap();
+    assert_eq!(appended.len(), 2);
+    assert!(matches!(
+        appended[1].content[0],
+        ContentBlock::ToolResult(_)
+    ));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_manager_loads_saves_and_deletes_sessions() {
+    let dir = temp_dir("manager");
+    let manager = FileSessionManager::new(&dir);
+
+    assert!(manager.load("missing").await.unwrap().is_none());
+    manager.delete("missing").await.unwrap();
+
+    let mut agent = Agent::new(
+        ScriptedModelProvider::new([reply("Hello.")]),
+        AgentArgs {
+            messages: vec![Message::new_user("Hi")],
+            session: session_args(&dir),
+            ..Default::default()
+        },
+    );
+    run_turn(agent.turn()).await;
+    assert!(manager.load("chat-1").await.unwrap().is_some());
+
+    manager.delete("chat-1").await.unwrap();
+    assert!(manager.load("chat-1").await.unwrap().is_none());
+
+    assert!(matches!(
+        manager.load("../chat-1").await,
+        Err(Error::InvalidSessionId(_))
+    ));
+    let restored = Agent::<()>::from_session(
+        ScriptedModelProvider::new([]),
+        AgentArgs::default(),
+        manager,
+        "chat-1",
+    )
+    .await;
+    assert!(matches!(restored, Err(Error::SessionNotFound(_))));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
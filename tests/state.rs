@@ -0,0 +1,210 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+#[cfg(feature = "sqlite")]
+use strands::state_provider::sqlite::SqliteStateProvider;
+use strands::state_provider::{MemoryStateProvider, StateProvider, file::FileStateProvider};
+
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+struct Preferences {
+    units: String,
+    cities: Vec<String>,
+}
+
+/// Checks the behavior every provider must share.
+async fn exercise(state: &dyn StateProvider) {
+    assert_eq!(state.get("city").await.unwrap(), None);
+    assert_eq!(state.delete("city").await.unwrap(), None);
+
+    state.set("city", json!("Oslo")).await.unwrap();
+    state.set("count", json!(1)).await.unwrap();
+    state.set("count", json!(2)).await.unwrap();
+    assert_eq!(state.get("city").await.unwrap(), Some(json!("Oslo")));
+    assert_eq!(state.get("count").await.unwrap(), Some(json!(2)));
+
+    let mut keys = state.list().await.unwrap();
+    keys.sort();
+    assert_eq!(keys, ["city", "count"]);
+    assert_eq!(
+        serde_json::Value::Object(state.snapshot().await.unwrap()),
+        json!({ "city": "Oslo", "count": 2 })
+    );
+
+    assert_eq!(state.delete("city").await.unwrap(), Some(json!("Oslo")));
+    assert_eq!(state.get("city").await.unwrap(), None);
+    assert_eq!(state.list().await.unwrap(), ["count"]);
+
+    let preferences = Preferences {
+        units: "metric".into(),
+        cities: vec!["Oslo".into(), "Bergen".into()],
+    };
+    state.set_as("preferences", &preferences).await.unwrap();
+    assert_eq!(
+        state.get_as::<Preferences>("preferences").await.unwrap(),
+        Some(preferences)
+    );
+    assert!(state.get_as::<Vec<u32>>("preferences").await.is_err());
+    assert_eq!(state.get_as::<Preferences>("missing").await.unwrap(), None);
+}
+
+/// Checks that two providers over one store never see each other's keys.
+async fn assert_isolated(first: &dyn StateProvider, second: &dyn StateProvider) {
+    first.set("city", json!("Oslo")).await.unwrap();
+    second.set("city", json!("Bergen")).await.unwrap();
+    second.set("units", json!("metric")).await.unwrap();
+
+    assert_eq!(first.get("city").await.unwrap(), Some(json!("Oslo")));
+    assert_eq!(second.get("city").await.unwrap(), Some(json!("Bergen")));
+    assert_eq!(first.get("units").await.unwrap(), None);
+    assert_eq!(first.list().await.unwrap(), ["city"]);
+
+    assert_eq!(first.delete("city").await.unwrap(), Some(json!("Oslo")));
+    assert_eq!(second.get("city").await.unwrap(), Some(json!("Bergen")));
+}
+
+/// A fresh directory for one test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("strands-state-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn memory_provider_stores_values() {
+    exercise(&MemoryStateProvider::new()).await;
+}
+
+#[tokio::test]
+async fn memory_provider_starts_from_existing_values() {
+    let state = MemoryStateProvider::from(
+        [("city".to_string(), json!("Oslo"))]
+            .into_iter()
+            .collect::<std::collections::HashMap<_, _>>(),
+    );
+
+    assert_eq!(state.get("city").await.unwrap(), Some(json!("Oslo")));
+}
+
+#[tokio::test]
+async fn file_provider_stores_values() {
+    let dir = temp_dir("values");
+    exercise(&FileStateProvider::new(dir.join("state.json"), "agent")).await;
+
+    // Writes leave no temporary files behind.
+    let files: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(files, ["state.json"]);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_provider_persists_values() {
+    let dir = temp_dir("persist");
+    let path = dir.join("state.json");
+
+    FileStateProvider::new(&path, "agent")
+        .set("city", json!("Oslo"))
+        .await
+        .unwrap();
+
+    let reopened = FileStateProvider::new(&path, "agent");
+    assert_eq!(reopened.get("city").await.unwrap(), Some(json!("Oslo")));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_provider_picks_up_external_changes() {
+    let dir = temp_dir("external");
+    let path = dir.join("state.json");
+    let state = FileStateProvider::new(&path, "agent");
+
+    state.set("city", json!("Oslo")).await.unwrap();
+    std::fs::write(&path, r#"{ "agent": { "city": "Trondheim" } }"#).unwrap();
+    assert_eq!(state.get("city").await.unwrap(), Some(json!("Trondheim")));
+
+    // A change that keeps the file's length, possibly within the same modification time.
+    std::fs::write(&path, r#"{ "agent": { "city": "Lillehammer" } }"#).unwrap();
+    assert_eq!(state.get("city").await.unwrap(), Some(json!("Lillehammer")));
+    std::fs::write(&path, r#"{ "agent": { "city": "Fredrikstad" } }"#).unwrap();
+    assert_eq!(state.get("city").await.unwrap(), Some(json!("Fredrikstad")));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn file_provider_isolates_namespaces() {
+    let dir = temp_dir("namespaces");
+    let path = dir.join("state.json");
+
+    assert_isolated(
+        &FileStateProvider::new(&path, "first"),
+        &FileStateProvider::new(&path, "second"),
+    )
+    .await;
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn file_provider_serializes_writes_to_one_file() {
+    let dir = temp_dir("concurrent");
+    // Both spellings of the path refer to the same file and must share its lock.
+    let first = FileStateProvider::new(dir.join("state.json"), "first");
+    std::fs::create_dir(dir.join("nested")).unwrap();
+    let second = FileStateProvider::new(dir.join("nested/../state.json"), "second");
+
+    let write = |state: FileStateProvider| {
+        tokio::spawn(async move {
+            for i in 0..20 {
+                state.set(&format!("key_{i}"), json!(i)).await.unwrap();
+            }
+            state
+        })
+    };
+    let (first, second) = (write(first), write(second));
+    let (first, second) = (first.await.unwrap(), second.await.unwrap());
+
+    assert_eq!(first.list().await.unwrap().len(), 20);
+    assert_eq!(second.list().await.unwrap().len(), 20);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_provider_stores_values() {
+    exercise(&SqliteStateProvider::open_in_memory("agent").unwrap()).await;
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_provider_persists_values() {
+    let dir = temp_dir("sqlite");
+    let path = dir.join("state.db");
+
+    SqliteStateProvider::open(&path, "agent")
+        .unwrap()
+        .set("city", json!("Oslo"))
+        .await
+        .unwrap();
+
+    let reopened = SqliteStateProvider::open(&path, "agent").unwrap();
+    assert_eq!(reopened.get("city").await.unwrap(), Some(json!("Oslo")));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn sqlite_provider_isolates_namespaces() {
+    let first = SqliteStateProvider::open_in_memory("first").unwrap();
+    let second = first.with_namespace("second");
+
+    assert_isolated(&first, &second).await;
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::model::{ScriptedModelProvider, Step, reply, tool_calls, tool_use};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::json;
+use strands::{
+    Error,
+    agent::{Agent, AgentArgs, CancellationToken, TurnOptions},
+    message::{ContentBlock, Message, TextBlock, ToolResultContent},
+    model::model_provider::{ModelPricing, ToolPolicy},
+};
+
+/// A weather forecast.
+#[derive(Debug, Deserialize, JsonSchema, PartialEq)]
+struct Forecast {
+    city: String,
+    temperature: i64,
+}
+
+fn forecast_agent(provider: ScriptedModelProvider) -> Agent<()> {
+    Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Forecast for Oslo?")],
+            ..Default::default()
+        },
+    )
+}
+
+fn output(id: &str, input: serde_json::Value) -> Vec<Step> {
+    tool_calls([tool_use(id, "structured_output", input)])
+}
+
+#[tokio::test]
+async fn returns_invalid_output_to_the_model_until_it_validates() {
+    let provider = ScriptedModelProvider::new([
+        output("call_1", json!({ "city": "Oslo" })),
+        output("call_2", json!({ "city": "Oslo", "temperature": 12 })),
+    ]);
+    let calls = Arc::clone(&provider.calls);
+    let mut agent = forecast_agent(provider);
+
+    let forecast: Forecast = agent.structured_output().await.unwrap();
+    assert_eq!(
+        forecast,
+        Forecast {
+            city: "Oslo".into(),
+            temperature: 12,
+        }
+    );
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 2);
+    for call in calls.iter() {
+        assert!(matches!(
+            &call.args.tool_policy,
+            Some(ToolPolicy::Specific { name }) if name == "structured_output"
+        ));
+        let specs = call.args.tool_specs.as_ref().unwrap();
+        assert_eq!(specs[0].name, "structured_output");
+        assert_eq!(specs[0].description.as_deref(), Some("A weather forecast."));
+    }
+
+    // The validation error is sent back as the result of the first attempt.
+    let ContentBlock::ToolResult(result) = &calls[1].messages[2].content[0] else {
+        panic!("expected a tool result");
+    };
+    assert_eq!(result.id, "call_1");
+    let Err(content) = &result.content else {
+        panic!("expected an error result");
+    };
+    assert!(matches!(
+        &content[0],
+        ToolResultContent::Text(TextBlock(text))
+            if text.starts_with("Invalid structured output: missing field `temperature`")
+    ));
+
+    // The accepted output is answered too, so the conversation can continue.
+    assert_eq!(agent.messages().len(), 5);
+}
+
+#[tokio::test]
+async fn gives_up_after_the_maximum_number_of_corrections() {
+    let provider = ScriptedModelProvider::new([
+        output("call_1", json!({ "city": "Oslo" })),
+        output("call_2", json!({ "temperature": 12 })),
+    ]);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Forecast for Oslo?")],
+            structured_output_retries: 1,
+            ..Default::default()
+        },
+    );
+
+    let error = agent.structured_output::<Forecast>().await.unwrap_err();
+    assert!(matches!(error, Error::StructuredOutput(message) if message.contains("`city`")));
+}
+
+#[tokio::test]
+async fn fails_when_the_model_answers_without_the_tool() {
+    let provider = ScriptedModelProvider::new([reply("It will be sunny.")]);
+    let mut agent = forecast_agent(provider);
+
+    let error = agent.structured_output::<Forecast>().await.unwrap_err();
+    assert!(matches!(error, Error::StructuredOutput(_)));
+}
+
+#[tokio::test]
+async fn surfaces_agent_errors_unwrapped() {
+    let provider = ScriptedModelProvider::new([]);
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Forecast for Oslo?")],
+            pricing: Some(ModelPricing::default()),
+            spend_limit: Some(0.0),
+            ..Default::default()
+        },
+    );
+
+    let error = agent.structured_output::<Forecast>().await.unwrap_err();
+    assert!(matches!(error, Error::SpendLimitExceeded { .. }));
+}
+
+#[tokio::test]
+async fn can_be_cancelled() {
+    let provider = ScriptedModelProvider::new([]);
+    let mut agent = forecast_agent(provider);
+
+    let cancellation = CancellationToken::new();
+    cancellation.cancel();
+
+    let error = agent
+        .structured_output_with::<Forecast>(TurnOptions {
+            cancellation,
+            ..Default::default()
+        })
+        .await
+        .unwrap_err();
+    assert!(matches!(error, Error::StructuredOutput(message) if message == "turn was cancelled"));
+}
@@ -0,0 +1,288 @@
+mod common;
+
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+
+use common::model::{ScriptedModelProvider, reply, run_turn, tool_calls, tool_use};
+use futures::StreamExt;
+use serde_json::json;
+use strands::{
+    agent::{Agent, AgentArgs, AgentEvent, ToolExecutor},
+    message::{ContentBlock, Message, TextBlock, ToolResult, ToolResultBlock, ToolResultContent},
+    tool::{Tool, ToolContext, ToolSpec},
+};
+
+/// Stores its `note` input in the agent state under the id of the tool use.
+struct NoteTool {
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Tool<()> for NoteTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "note".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        input: &serde_json::Map<String, serde_json::Value>,
+        context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        assert_eq!(context.tool_use.name, "note");
+        assert_eq!(context.tool_use.input, json!(input));
+
+        context
+            .state
+            .set(&context.tool_use.id, input["note"].clone())
+            .await
+            .unwrap();
+
+        Ok(Ok(vec![ToolResultContent::Text(TextBlock("Noted".into()))]))
+    }
+}
+
+/// Fails every call.
+struct FailingTool;
+
+#[async_trait::async_trait]
+impl Tool<()> for FailingTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "fail".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        _input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        Err(())
+    }
+}
+
+/// Sleeps for `delay_ms` and records how many calls were running at the same time.
+#[derive(Clone, Default)]
+struct SlowTool {
+    running: Arc<AtomicUsize>,
+    max_running: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl Tool<()> for SlowTool {
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "slow".into(),
+            ..Default::default()
+        }
+    }
+
+    async fn invoke(
+        &self,
+        input: &serde_json::Map<String, serde_json::Value>,
+        _context: &ToolContext,
+    ) -> Result<ToolResult, ()> {
+        let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_running.fetch_max(running, Ordering::SeqCst);
+
+        let delay = input["delay_ms"].as_u64().unwrap();
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+
+        self.running.fetch_sub(1, Ordering::SeqCst);
+        Ok(Ok(vec![ToolResultContent::Text(TextBlock(format!(
+            "Slept {delay}ms"
+        )))]))
+    }
+}
+
+/// Runs a turn in which the model calls the slow tool once per delay, and returns the ids of the
+/// started and completed tool calls in event order.
+async fn run_slow_tools(
+    tool: &SlowTool,
+    executor: Option<ToolExecutor>,
+    delays: &[u64],
+) -> (Agent<()>, Vec<String>, Vec<String>) {
+    let provider = ScriptedModelProvider::new([
+        tool_calls(delays.iter().enumerate().map(|(index, delay)| {
+            tool_use(
+                &format!("call_{index}"),
+                "slow",
+                json!({ "delay_ms": delay }),
+            )
+        })),
+        reply("Done."),
+    ]);
+
+    let mut args = AgentArgs {
+        messages: vec![Message::new_user("Sleep")],
+        tools: vec![tool.clone().boxed()],
+        ..Default::default()
+    };
+    if let Some(executor) = executor {
+        args.tool_executor = executor;
+    }
+    let mut agent = Agent::new(provider, args);
+
+    let mut started = Vec::new();
+    let mut completed = Vec::new();
+    let mut stream = agent.turn();
+    while let Some(event) = stream.next().await {
+        match event.unwrap() {
+            AgentEvent::ToolCallStarted { tool_use } => started.push(tool_use.id),
+            AgentEvent::ToolCallCompleted { result, .. } => completed.push(result.id),
+            _ => {}
+        }
+    }
+    drop(stream);
+
+    (agent, started, completed)
+}
+
+/// Returns the tool results sent back to the model after the first tool call.
+fn tool_results(messages: &[Message]) -> Vec<ToolResultBlock> {
+    messages[2]
+        .content
+        .iter()
+        .filter_map(|block| match block {
+            ContentBlock::ToolResult(result) => Some(result.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn result_text(result: &ToolResultBlock) -> &str {
+    let content = result.content.as_ref().unwrap_or_else(|e| e);
+    match &content[0] {
+        ToolResultContent::Text(TextBlock(text)) => text,
+        other => panic!("unexpected tool result content: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn invokes_tools_by_name_with_their_context() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let provider = ScriptedModelProvider::new([
+        tool_calls([tool_use("call_1", "note", json!({ "note": "buy milk" }))]),
+        reply("Noted."),
+    ]);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Remember to buy milk")],
+            tools: vec![
+                FailingTool.boxed(),
+                NoteTool {
+                    calls: Arc::clone(&calls),
+                }
+                .boxed(),
+            ],
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        agent.state().get("call_1").await.unwrap(),
+        Some(json!("buy milk"))
+    );
+
+    let results = tool_results(&agent.messages());
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].id, "call_1");
+    assert!(results[0].content.is_ok());
+    assert_eq!(result_text(&results[0]), "Noted");
+}
+
+#[tokio::test]
+async fn reports_tool_errors_and_unknown_tools_to_the_model() {
+    let provider = ScriptedModelProvider::new([
+        tool_calls([
+            tool_use("call_1", "fail", json!({})),
+            tool_use("call_2", "missing", json!({})),
+        ]),
+        reply("Both failed."),
+    ]);
+    let model_calls = Arc::clone(&provider.calls);
+
+    let mut agent = Agent::new(
+        provider,
+        AgentArgs {
+            messages: vec![Message::new_user("Try both tools")],
+            tools: vec![FailingTool.boxed()],
+            ..Default::default()
+        },
+    );
+
+    run_turn(agent.turn()).await;
+
+    let results = tool_results(&agent.messages());
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].id, "call_1");
+    assert!(results[0].content.is_err());
+    assert_eq!(result_text(&results[0]), "Tool fail failed: ()");
+    assert_eq!(results[1].id, "call_2");
+    assert!(results[1].content.is_err());
+    assert_eq!(result_text(&results[1]), "Tool missing not found");
+
+    // The failures are sent back to the model rather than ending the turn.
+    let model_calls = model_calls.lock().unwrap();
+    assert_eq!(model_calls.len(), 2);
+    assert_eq!(tool_results(&model_calls[1].messages).len(), 2);
+}
+
+#[tokio::test]
+async fn runs_tools_sequentially_by_default() {
+    let tool = SlowTool::default();
+    let (_, started, completed) = run_slow_tools(&tool, None, &[30, 10, 20]).await;
+
+    assert_eq!(tool.max_running.load(Ordering::SeqCst), 1);
+    assert_eq!(started, ["call_0", "call_1", "call_2"]);
+    assert_eq!(completed, ["call_0", "call_1", "call_2"]);
+}
+
+#[tokio::test]
+async fn bounds_concurrent_tool_calls() {
+    let tool = SlowTool::default();
+    let (_, _, completed) = run_slow_tools(
+        &tool,
+        Some(ToolExecutor::Concurrent { max_concurrency: 2 }),
+        &[60, 30, 10, 40],
+    )
+    .await;
+
+    assert_eq!(tool.max_running.load(Ordering::SeqCst), 2);
+    // Shorter calls finish first.
+    assert_eq!(completed, ["call_1", "call_2", "call_0", "call_3"]);
+}
+
+#[tokio::test]
+async fn orders_tool_results_like_the_tool_uses() {
+    let tool = SlowTool::default();
+    let (agent, _, completed) = run_slow_tools(
+        &tool,
+        Some(ToolExecutor::Concurrent { max_concurrency: 8 }),
+        &[30, 20, 10],
+    )
+    .await;
+
+    assert_eq!(tool.max_running.load(Ordering::SeqCst), 3);
+    assert_eq!(completed, ["call_2", "call_1", "call_0"]);
+
+    let results = tool_results(&agent.messages());
+    let ids: Vec<_> = results.iter().map(|result| result.id.as_str()).collect();
+    assert_eq!(ids, ["call_0", "call_1", "call_2"]);
+    assert_eq!(result_text(&results[0]), "Slept 30ms");
+}